@@ -6,6 +6,7 @@ use reqwest::StatusCode;
 use serde::Deserialize;
 use tap::Pipe;
 use tl::ParserOptions;
+use tracing::debug;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -50,6 +51,14 @@ pub struct CaptionInfo {
 
 impl CaptionInfo {
     pub async fn download(&self, selector: &str) -> Result<Option<Bytes>, Error> {
+        debug!(
+            anime_no = self.anime_no,
+            episode = %self.episode,
+            translator = %self.translator,
+            website = %self.website,
+            "Downloading caption"
+        );
+
         let website_resp = reqwest::get(&self.website).await?;
 
         if !website_resp.status().is_success() {
@@ -70,7 +79,10 @@ impl CaptionInfo {
             .and_then(|r| r.next()?.get(&parser)?.as_tag()?.pipe(Some))
         {
             Some(r) => r,
-            None => return Ok(None),
+            None => {
+                debug!(website = %self.website, selector, "No caption link");
+                return Ok(None);
+            }
         };
 
         let href = match element
@@ -104,6 +116,8 @@ const ANISSIA_URL: &str = "https://api.anissia.net";
 pub async fn get_recent_captions(page: usize) -> Result<Vec<CaptionInfo>, Error> {
     let url = format!("{}/anime/caption/recent/{}", ANISSIA_URL, page);
 
+    debug!(page, "Fetching recent captions");
+
     let resp = reqwest::get(url).await?;
 
     if !resp.status().is_success() {
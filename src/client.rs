@@ -0,0 +1,204 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::{
+    config::{Config, SeedingPolicy},
+    qbittorrent::Qbittorrent,
+    transmission::Transmission,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("transmission: {0}")]
+    Transmission(Box<dyn std::error::Error + Send + Sync>),
+    #[error("reqwest: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    #[error("HTTP {0}")]
+    Status(reqwest::StatusCode),
+    #[error("login failed")]
+    Login,
+    /// the client didn't take the torrent, e.g. an invalid link
+    #[error("refused the torrent: {0}")]
+    Refused(String),
+}
+
+/// Which download client trss drives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientKind {
+    #[default]
+    Transmission,
+    Qbittorrent,
+}
+
+impl FromStr for ClientKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transmission" => Ok(Self::Transmission),
+            "qbittorrent" => Ok(Self::Qbittorrent),
+            _ => Err("expected `transmission` or `qbittorrent`".to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for ClientKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let x = match self {
+            Self::Transmission => "Transmission",
+            Self::Qbittorrent => "qBittorrent",
+        };
+
+        write!(f, "{x}")
+    }
+}
+
+/// Status of a torrent, in Transmission's terms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TorrentStatus {
+    Stopped,
+    QueuedToVerify,
+    Verifying,
+    QueuedToDownload,
+    Downloading,
+    QueuedToSeed,
+    Seeding,
+}
+
+impl fmt::Display for TorrentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let x = match self {
+            Self::Stopped => "stopped",
+            Self::QueuedToVerify => "queued_to_verify",
+            Self::Verifying => "verifying",
+            Self::QueuedToDownload => "queued_to_download",
+            Self::Downloading => "downloading",
+            Self::QueuedToSeed => "queued_to_seed",
+            Self::Seeding => "seeding",
+        };
+
+        write!(f, "{x}")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Torrent {
+    /// infohash, lowercase hex
+    pub hash: String,
+    pub name: String,
+    /// `None` if the client didn't say, as for duplicates reported by Transmission
+    pub status: Option<TorrentStatus>,
+    pub labels: Vec<String>,
+    /// 0 to 1
+    pub percent_done: f32,
+    pub upload_ratio: f32,
+    pub metadata_complete: bool,
+    /// paths inside the torrent, starting with the top-level folder of multi-file torrents; only
+    /// listed when asked for, and empty without metadata
+    pub files: Vec<String>,
+}
+
+impl Torrent {
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels.iter().any(|x| x == label)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AddTorrent<'a> {
+    /// magnet link or URL of a `.torrent`
+    pub link: &'a str,
+    /// infohash, if the feed or the magnet link tells it
+    pub hash: Option<&'a str>,
+    pub directory: &'a Path,
+    pub labels: Vec<String>,
+    /// `Cookie` header for fetching the `.torrent`
+    pub cookies: Option<String>,
+    pub seeding: SeedingPolicy,
+}
+
+#[derive(Debug, Clone)]
+pub enum Added {
+    New(Torrent),
+    /// the client already had it; only the name and hash are known
+    Duplicate(Torrent),
+}
+
+/// Session-wide settings applied on connecting. `None` leaves the client's own.
+#[derive(Debug, Clone, Default)]
+pub struct SessionSettings {
+    pub download_dir: Option<String>,
+    /// KB/s
+    pub speed_limit_up: Option<i32>,
+    /// KB/s
+    pub speed_limit_down: Option<i32>,
+    pub download_queue_size: Option<i32>,
+    pub seed_queue_size: Option<i32>,
+}
+
+impl SessionSettings {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            download_dir: config.download_dir.clone(),
+            speed_limit_up: config.speed_limit_up,
+            speed_limit_down: config.speed_limit_down,
+            download_queue_size: config.download_queue_size,
+            seed_queue_size: config.seed_queue_size,
+        }
+    }
+}
+
+/// A download client trss adds, renames and removes torrents in.
+///
+/// Torrents are identified by their infohash.
+#[async_trait]
+pub trait DownloadClient: Send + Sync {
+    fn kind(&self) -> ClientKind;
+
+    async fn version(&self) -> Result<String, ClientError>;
+
+    async fn configure(&self, settings: &SessionSettings) -> Result<(), ClientError>;
+
+    /// Adds a torrent and applies its seeding policy.
+    ///
+    /// Once the torrent is added, failing to apply the policy is only logged, so that it still
+    /// makes it into the history.
+    async fn add(&self, torrent: AddTorrent<'_>) -> Result<Added, ClientError>;
+
+    /// Looks up `hashes`, or every torrent if `None`, with their files if `files`.
+    async fn get(
+        &self,
+        hashes: Option<&[String]>,
+        files: bool,
+    ) -> Result<Vec<Torrent>, ClientError>;
+
+    /// Renames the file or folder at `path` inside the torrent to `name`, returning whether the
+    /// client did.
+    async fn rename_path(&self, hash: &str, path: &str, name: &str) -> Result<bool, ClientError>;
+
+    /// Doesn't download the files at `indices` of [`Torrent::files`].
+    async fn skip_files(&self, hash: &str, indices: &[usize]) -> Result<(), ClientError>;
+
+    async fn stop(&self, hashes: &[String]) -> Result<(), ClientError>;
+
+    async fn remove(&self, hashes: &[String], delete_data: bool) -> Result<(), ClientError>;
+
+    /// Replaces the labels of a torrent.
+    async fn set_labels(&self, hash: &str, labels: &[String]) -> Result<(), ClientError>;
+
+    /// Moves the data of a torrent to `directory`.
+    async fn set_location(&self, hash: &str, directory: &Path) -> Result<(), ClientError>;
+}
+
+/// The client `DOWNLOAD_CLIENT` picks, not connected yet.
+pub fn from_config(config: &Config) -> Result<Box<dyn DownloadClient>, ClientError> {
+    let client: Box<dyn DownloadClient> = match config.download_client {
+        ClientKind::Transmission => Box::new(Transmission::new(config)?),
+        ClientKind::Qbittorrent => Box::new(Qbittorrent::new(config)?),
+    };
+
+    Ok(client)
+}
@@ -0,0 +1,650 @@
+use std::collections::BTreeMap;
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use url::Url;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FeedError {
+    #[error("rss: {0}")]
+    Rss(#[from] rss::Error),
+
+    #[error("atom: {0}")]
+    Atom(#[from] atom_syndication::Error),
+
+    #[error("json feed: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Rss,
+    Atom,
+    JsonFeed,
+}
+
+impl Format {
+    /// From `content_type` if it names a feed format, otherwise from the root of `body`.
+    pub fn detect(content_type: Option<&str>, body: &[u8]) -> Self {
+        let content_type = content_type.unwrap_or_default().to_lowercase();
+
+        if content_type.contains("atom") {
+            return Self::Atom;
+        }
+        if content_type.contains("json") {
+            return Self::JsonFeed;
+        }
+        if content_type.contains("rss") {
+            return Self::Rss;
+        }
+
+        let body = String::from_utf8_lossy(&body[..body.len().min(4096)]);
+        let body = body.trim_start_matches('\u{feff}').trim_start();
+
+        if body.starts_with('{') {
+            return Self::JsonFeed;
+        }
+
+        // the first element that isn't a declaration, comment or doctype
+        let root = body
+            .match_indices('<')
+            .map(|(i, _)| &body[i + 1..])
+            .find(|x| !x.starts_with('?') && !x.starts_with('!'))
+            .and_then(|x| x.split(|c: char| c.is_whitespace() || c == '>').next())
+            .unwrap_or_default();
+
+        if root == "feed" || root.ends_with(":feed") {
+            Self::Atom
+        } else {
+            Self::Rss
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Enclosure {
+    pub url: String,
+    pub length: Option<u64>,
+    pub mime_type: Option<String>,
+}
+
+/// An entry of any supported feed format.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedItem {
+    pub title: String,
+    pub link: Option<String>,
+    pub guid: Option<String>,
+    /// as written in the feed (RFC 2822 for RSS, RFC 3339 otherwise)
+    pub published: Option<String>,
+    /// bytes
+    pub size: Option<u64>,
+    /// lowercase hex
+    pub infohash: Option<String>,
+    pub enclosure: Option<Enclosure>,
+    /// extension elements by namespace prefix and name, e.g. `nyaa` → `infoHash`
+    pub extensions: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl FeedItem {
+    /// Text of the extension element `prefix:name`.
+    pub fn extension(&self, prefix: &str, name: &str) -> Option<&str> {
+        self.extensions.get(prefix)?.get(name).map(String::as_str)
+    }
+
+    /// Where to get the item's torrent from.
+    ///
+    /// Prefers the enclosure, then the `torrent:magnetURI`, then `link` if it's a magnet or
+    /// `.torrent` link, then a magnet link made from the infohash, then any other `link`.
+    /// Enclosures that aren't torrents, e.g. cover images, are ignored.
+    pub fn torrent_link(&self) -> Option<TorrentLink> {
+        let enclosure = self
+            .enclosure
+            .as_ref()
+            .filter(|x| !x.url.is_empty())
+            .filter(|x| {
+                x.url.starts_with("magnet:")
+                    || x.mime_type
+                        .as_deref()
+                        .is_none_or(|x| x.contains("bittorrent"))
+            })
+            .map(|x| x.url.clone());
+
+        let magnet = || {
+            self.extension("torrent", "magnetURI")
+                .filter(|x| parse_hash(x).is_some())
+                .map(|x| x.to_owned())
+        };
+
+        // a `.torrent` carries the trackers, which a magnet link made from the infohash lacks
+        let torrent_url = || self.link.clone().filter(|x| is_torrent_url(x));
+
+        let from_infohash = || {
+            let hash = self.infohash.as_deref()?;
+            let name = utf8_percent_encode(&self.title, NON_ALPHANUMERIC);
+
+            Some(format!("magnet:?xt=urn:btih:{hash}&dn={name}"))
+        };
+
+        let url = enclosure
+            .or_else(magnet)
+            .or_else(torrent_url)
+            .or_else(from_infohash)
+            .or_else(|| self.link.clone().filter(|x| !x.is_empty()))?;
+
+        let hash = parse_hash(&url).or_else(|| self.infohash.clone());
+
+        Some(TorrentLink { url, hash })
+    }
+}
+
+/// A .torrent URL or magnet link, with its infohash if it's known without fetching the torrent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentLink {
+    pub url: String,
+    /// lowercase hex
+    pub hash: Option<String>,
+}
+
+/// Whether `link` is a magnet link or a URL ending with `.torrent`, rather than e.g. a web page.
+fn is_torrent_url(link: &str) -> bool {
+    link.starts_with("magnet:")
+        || Url::parse(link).is_ok_and(|x| x.path().to_ascii_lowercase().ends_with(".torrent"))
+}
+
+/// Infohash of a magnet link, as lowercase hex. Both hex and base32 `btih` are supported.
+pub fn parse_hash(magnet: &str) -> Option<String> {
+    magnet
+        .strip_prefix("magnet:?")?
+        .split('&')
+        .find_map(|x| x.strip_prefix("xt=urn:btih:"))
+        .and_then(normalize_hash)
+}
+
+/// A 40 character hex or 32 character base32 infohash as lowercase hex.
+fn normalize_hash(hash: &str) -> Option<String> {
+    let hash = hash.trim();
+
+    match hash.len() {
+        40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => Some(hash.to_lowercase()),
+        32 => {
+            let mut bytes = Vec::with_capacity(20);
+            let mut buf = 0u64;
+            let mut bits = 0;
+
+            for c in hash.chars() {
+                let value = match c.to_ascii_uppercase() {
+                    c @ 'A'..='Z' => c as u64 - 'A' as u64,
+                    c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+                    _ => return None,
+                };
+
+                buf = (buf << 5) | value;
+                bits += 5;
+
+                if bits >= 8 {
+                    bits -= 8;
+                    bytes.push((buf >> bits) as u8);
+                    buf &= (1 << bits) - 1;
+                }
+            }
+
+            Some(bytes.iter().map(|x| format!("{x:02x}")).collect())
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub format: Format,
+    pub title: String,
+    pub link: String,
+    /// minutes the feed may be cached for (RSS `<ttl>`)
+    pub ttl: Option<u64>,
+    pub items: Vec<FeedItem>,
+}
+
+impl Feed {
+    pub fn parse(content_type: Option<&str>, body: &[u8]) -> Result<Self, FeedError> {
+        let feed = match Format::detect(content_type, body) {
+            Format::Rss => Self::from(rss::Channel::read_from(body)?),
+            Format::Atom => Self::from(atom_syndication::Feed::read_from(body)?),
+            Format::JsonFeed => Self::from(serde_json::from_slice::<JsonFeed>(body)?),
+        };
+
+        Ok(feed)
+    }
+}
+
+/// Parses sizes like `1.2 GiB` (nyaa) or `734003200`.
+fn parse_size(x: &str) -> Option<u64> {
+    let x = x.trim();
+    let split = x
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(x.len());
+    let (number, unit) = x.split_at(split);
+
+    let multiplier = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1u64,
+        "kb" => 1000,
+        "kib" => 1 << 10,
+        "mb" => 1000 * 1000,
+        "mib" => 1 << 20,
+        "gb" => 1000 * 1000 * 1000,
+        "gib" => 1 << 30,
+        "tb" => 1000 * 1000 * 1000 * 1000,
+        "tib" => 1 << 40,
+        _ => return None,
+    };
+
+    Some((number.parse::<f64>().ok()? * multiplier as f64) as u64)
+}
+
+impl From<rss::Channel> for Feed {
+    fn from(channel: rss::Channel) -> Self {
+        let items = channel
+            .items()
+            .iter()
+            .map(|item| {
+                let extensions = item
+                    .extensions()
+                    .iter()
+                    .map(|(prefix, elements)| {
+                        let elements = elements
+                            .iter()
+                            .filter_map(|(name, values)| {
+                                Some((name.clone(), values.first()?.value()?.trim().to_owned()))
+                            })
+                            .collect();
+
+                        (prefix.clone(), elements)
+                    })
+                    .collect::<BTreeMap<_, BTreeMap<_, _>>>();
+
+                let enclosure = item.enclosure().map(|x| Enclosure {
+                    url: x.url().to_owned(),
+                    length: x.length().parse().ok().filter(|x| *x > 0),
+                    mime_type: Some(x.mime_type().to_owned()).filter(|x| !x.is_empty()),
+                });
+
+                let mut item = FeedItem {
+                    title: item.title().unwrap_or_default().to_owned(),
+                    link: item.link().map(|x| x.to_owned()),
+                    guid: item.guid().map(|x| x.value().to_owned()),
+                    published: item.pub_date().map(|x| x.to_owned()),
+                    size: None,
+                    infohash: None,
+                    enclosure,
+                    extensions,
+                };
+
+                item.size = item
+                    .enclosure
+                    .as_ref()
+                    .and_then(|x| x.length)
+                    .or_else(|| {
+                        item.extension("torrent", "contentLength")
+                            .and_then(parse_size)
+                    })
+                    .or_else(|| item.extension("nyaa", "size").and_then(parse_size));
+                item.infohash = item
+                    .extension("nyaa", "infoHash")
+                    .or_else(|| item.extension("torrent", "infoHash"))
+                    .and_then(normalize_hash)
+                    .or_else(|| item.extension("torrent", "magnetURI").and_then(parse_hash));
+
+                item
+            })
+            .collect();
+
+        Self {
+            format: Format::Rss,
+            title: channel.title().to_owned(),
+            link: channel.link().to_owned(),
+            ttl: channel.ttl().and_then(|x| x.trim().parse().ok()),
+            items,
+        }
+    }
+}
+
+impl From<atom_syndication::Feed> for Feed {
+    fn from(feed: atom_syndication::Feed) -> Self {
+        fn alternate(links: &[atom_syndication::Link]) -> Option<&atom_syndication::Link> {
+            links
+                .iter()
+                .find(|x| x.rel() == "alternate")
+                .or_else(|| links.iter().find(|x| x.rel() != "enclosure"))
+        }
+
+        let items = feed
+            .entries()
+            .iter()
+            .map(|entry| {
+                let enclosure = entry
+                    .links()
+                    .iter()
+                    .find(|x| x.rel() == "enclosure")
+                    .map(|x| Enclosure {
+                        url: x.href().to_owned(),
+                        length: x.length().and_then(|x| x.parse().ok()),
+                        mime_type: x.mime_type().map(|x| x.to_owned()),
+                    });
+
+                FeedItem {
+                    title: entry.title().value.clone(),
+                    link: alternate(entry.links()).map(|x| x.href().to_owned()),
+                    guid: Some(entry.id().to_owned()),
+                    published: Some(entry.published().unwrap_or(entry.updated()).to_rfc3339()),
+                    size: enclosure.as_ref().and_then(|x| x.length),
+                    infohash: None,
+                    enclosure,
+                    extensions: BTreeMap::new(),
+                }
+            })
+            .collect();
+
+        Self {
+            format: Format::Atom,
+            title: feed.title().value.clone(),
+            link: alternate(feed.links())
+                .map(|x| x.href().to_owned())
+                .unwrap_or_else(|| feed.id().to_owned()),
+            ttl: None,
+            items,
+        }
+    }
+}
+
+/// <https://www.jsonfeed.org/version/1.1/>
+#[derive(Debug, Deserialize)]
+struct JsonFeed {
+    #[serde(default)]
+    title: String,
+    home_page_url: Option<String>,
+    feed_url: Option<String>,
+    #[serde(default)]
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonFeedItem {
+    id: serde_json::Value,
+    url: Option<String>,
+    #[serde(default)]
+    title: String,
+    date_published: Option<String>,
+    #[serde(default)]
+    attachments: Vec<JsonFeedAttachment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonFeedAttachment {
+    url: String,
+    mime_type: Option<String>,
+    size_in_bytes: Option<u64>,
+}
+
+impl From<JsonFeed> for Feed {
+    fn from(feed: JsonFeed) -> Self {
+        let items = feed
+            .items
+            .into_iter()
+            .map(|item| {
+                let enclosure = item.attachments.into_iter().next().map(|x| Enclosure {
+                    url: x.url,
+                    length: x.size_in_bytes,
+                    mime_type: x.mime_type,
+                });
+
+                FeedItem {
+                    title: item.title,
+                    link: item.url,
+                    // 1.0 allowed numbers
+                    guid: match item.id {
+                        serde_json::Value::String(x) => Some(x),
+                        serde_json::Value::Null => None,
+                        x => Some(x.to_string()),
+                    },
+                    published: item.date_published,
+                    size: enclosure.as_ref().and_then(|x| x.length),
+                    infohash: None,
+                    enclosure,
+                    extensions: BTreeMap::new(),
+                }
+            })
+            .collect();
+
+        Self {
+            format: Format::JsonFeed,
+            title: feed.title,
+            link: feed.home_page_url.or(feed.feed_url).unwrap_or_default(),
+            ttl: None,
+            items,
+        }
+    }
+}
+
+#[test]
+fn test_detect_format() {
+    assert_eq!(
+        Format::Atom,
+        Format::detect(Some("application/atom+xml; charset=utf-8"), b"")
+    );
+    assert_eq!(
+        Format::JsonFeed,
+        Format::detect(Some("application/feed+json"), b"")
+    );
+    assert_eq!(
+        Format::Atom,
+        Format::detect(
+            Some("text/xml"),
+            b"<?xml version=\"1.0\"?>\n<!-- generated -->\n<feed xmlns=\"http://www.w3.org/2005/Atom\">"
+        )
+    );
+    assert_eq!(
+        Format::Rss,
+        Format::detect(None, b"<?xml version=\"1.0\"?><rss version=\"2.0\">")
+    );
+    assert_eq!(
+        Format::JsonFeed,
+        Format::detect(None, b"\n  {\"version\": \"\"}")
+    );
+}
+
+#[test]
+fn test_parse_feeds() {
+    let rss = br#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:nyaa="https://nyaa.si/xmlns/nyaa">
+  <channel>
+    <title>Nyaa</title>
+    <link>https://nyaa.si/</link>
+    <description>Nyaa</description>
+    <ttl>10</ttl>
+    <item>
+      <title>[SubsPlease] Slime - 62 (1080p) [0214B01E].mkv</title>
+      <link>https://nyaa.si/download/1.torrent</link>
+      <guid isPermaLink="true">https://nyaa.si/view/1</guid>
+      <pubDate>Tue, 01 Apr 2025 12:00:00 -0000</pubDate>
+      <nyaa:infoHash>DED9F1C3AB1E1EF9A5E06F3E8E2C8B3F5A1E5D2C</nyaa:infoHash>
+      <nyaa:size>1.5 GiB</nyaa:size>
+    </item>
+  </channel>
+</rss>"#;
+
+    let atom = br#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Indexer</title>
+  <id>urn:uuid:1</id>
+  <updated>2025-04-01T12:00:00Z</updated>
+  <link rel="alternate" href="https://indexer.example.com/"/>
+  <entry>
+    <title>[SubsPlease] Slime - 62 (1080p) [0214B01E].mkv</title>
+    <id>urn:uuid:2</id>
+    <updated>2025-04-01T12:00:00Z</updated>
+    <link rel="alternate" href="https://indexer.example.com/view/1"/>
+    <link rel="enclosure" type="application/x-bittorrent" length="1610612736" href="https://indexer.example.com/download/1.torrent"/>
+  </entry>
+</feed>"#;
+
+    let json = br#"{
+  "version": "https://jsonfeed.org/version/1.1",
+  "title": "Indexer",
+  "home_page_url": "https://indexer.example.com/",
+  "items": [
+    {
+      "id": "1",
+      "url": "https://indexer.example.com/view/1",
+      "title": "[SubsPlease] Slime - 62 (1080p) [0214B01E].mkv",
+      "date_published": "2025-04-01T12:00:00Z",
+      "attachments": [
+        { "url": "https://indexer.example.com/download/1.torrent", "mime_type": "application/x-bittorrent", "size_in_bytes": 1610612736 }
+      ]
+    }
+  ]
+}"#;
+
+    let rss = Feed::parse(Some("application/rss+xml"), rss).unwrap();
+    let atom = Feed::parse(None, atom).unwrap();
+    let json = Feed::parse(None, json).unwrap();
+
+    assert_eq!(Format::Rss, rss.format);
+    assert_eq!(Format::Atom, atom.format);
+    assert_eq!(Format::JsonFeed, json.format);
+
+    for feed in [&rss, &atom, &json] {
+        assert_eq!(1, feed.items.len());
+        assert_eq!(
+            "[SubsPlease] Slime - 62 (1080p) [0214B01E].mkv",
+            feed.items[0].title
+        );
+        assert_eq!(Some(1610612736), feed.items[0].size);
+    }
+
+    assert_eq!("https://nyaa.si/", rss.link);
+    assert_eq!(Some(10), rss.ttl);
+    assert_eq!(
+        Some("ded9f1c3ab1e1ef9a5e06f3e8e2c8b3f5a1e5d2c"),
+        rss.items[0].infohash.as_deref()
+    );
+    assert_eq!(
+        Some("https://indexer.example.com/download/1.torrent"),
+        atom.items[0].enclosure.as_ref().map(|x| x.url.as_str())
+    );
+    assert_eq!(
+        Some("https://indexer.example.com/view/1"),
+        json.items[0].link.as_deref()
+    );
+}
+
+#[test]
+fn test_parse_hash() {
+    let magnet = "magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH5&dn=%5BSubsPlease%5D%20Tensei%20Shitara%20Slime%20Datta%20Ken%20-%2062%20%281080p%29%20%5B0214B01E%5D.mkv&xl=1497945704&tr=http%3A%2F%2Fnyaa.tracker.wf%3A7777%2Fannounce&tr=udp%3A%2F%2Ftracker.opentrackr.org%3A1337%2Fannounce";
+
+    assert_eq!(
+        Some("d9fe2fdfa060a246cd86d6ca566ddc20031644fd"),
+        parse_hash(magnet).as_deref()
+    );
+
+    let magnet = "magnet:?dn=Slime&xt=urn:btih:D9FE2FDFA060A246CD86D6CA566DDC20031644FD&";
+
+    assert_eq!(
+        Some("d9fe2fdfa060a246cd86d6ca566ddc20031644fd"),
+        parse_hash(magnet).as_deref()
+    );
+
+    assert!(parse_hash("magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH").is_none());
+    assert!(parse_hash("https://google.com").is_none());
+}
+
+#[test]
+fn test_torrent_link() {
+    let hash = "d9fe2fdfa060a246cd86d6ca566ddc20031644fd";
+    let item = FeedItem {
+        title: "[SubsPlease] Slime - 62 (1080p)".to_owned(),
+        link: Some("https://nyaa.si/view/1".to_owned()),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        Some(TorrentLink {
+            url: "https://nyaa.si/view/1".to_owned(),
+            hash: None
+        }),
+        item.torrent_link()
+    );
+
+    let item = FeedItem {
+        infohash: Some(hash.to_owned()),
+        ..item
+    };
+
+    assert_eq!(
+        Some(TorrentLink {
+            url: format!(
+                "magnet:?xt=urn:btih:{hash}&dn=%5BSubsPlease%5D%20Slime%20%2D%2062%20%281080p%29"
+            ),
+            hash: Some(hash.to_owned())
+        }),
+        item.torrent_link()
+    );
+
+    // a `.torrent` link is kept over a magnet link without trackers
+    let torrent = FeedItem {
+        link: Some("https://nyaa.si/download/1.torrent".to_owned()),
+        ..item.clone()
+    };
+
+    assert_eq!(
+        Some(TorrentLink {
+            url: "https://nyaa.si/download/1.torrent".to_owned(),
+            hash: Some(hash.to_owned())
+        }),
+        torrent.torrent_link()
+    );
+
+    let magnet = "magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH5";
+    let mut item = FeedItem {
+        infohash: None,
+        ..item
+    };
+    item.extensions.insert(
+        "torrent".to_owned(),
+        BTreeMap::from([("magnetURI".to_owned(), magnet.to_owned())]),
+    );
+
+    assert_eq!(
+        Some(TorrentLink {
+            url: magnet.to_owned(),
+            hash: Some(hash.to_owned())
+        }),
+        item.torrent_link()
+    );
+
+    let item = FeedItem {
+        enclosure: Some(Enclosure {
+            url: "https://nyaa.si/download/1.torrent".to_owned(),
+            length: None,
+            mime_type: Some("application/x-bittorrent".to_owned()),
+        }),
+        ..item
+    };
+
+    assert_eq!(
+        Some("https://nyaa.si/download/1.torrent"),
+        item.torrent_link().map(|x| x.url).as_deref()
+    );
+
+    let item = FeedItem {
+        title: String::new(),
+        link: None,
+        enclosure: Some(Enclosure {
+            url: "https://nyaa.si/cover.jpg".to_owned(),
+            length: None,
+            mime_type: Some("image/jpeg".to_owned()),
+        }),
+        extensions: BTreeMap::new(),
+        ..Default::default()
+    };
+
+    assert!(item.torrent_link().is_none());
+}
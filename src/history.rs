@@ -0,0 +1,504 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+#[derive(Debug, thiserror::Error)]
+pub enum HistoryError {
+    #[error("sqlite: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Schema migrations, applied in order and tracked with `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    "
+CREATE TABLE items (
+    hash       TEXT PRIMARY KEY NOT NULL,
+    guid       TEXT,
+    title      TEXT NOT NULL,
+    link       TEXT NOT NULL,
+    channel    TEXT NOT NULL,
+    rule       TEXT NOT NULL,
+    directory  TEXT NOT NULL,
+    file_name  TEXT,
+    added_at   INTEGER NOT NULL,
+    seen_at    INTEGER NOT NULL,
+    renamed_at INTEGER,
+    removed_at INTEGER
+);
+CREATE INDEX items_channel ON items (channel);
+CREATE INDEX items_link ON items (link);
+",
+    "
+ALTER TABLE items ADD COLUMN rename_failed_at INTEGER;
+",
+    "
+ALTER TABLE items ADD COLUMN completed_at INTEGER;
+",
+    "
+CREATE TABLE feeds (
+    url           TEXT PRIMARY KEY NOT NULL,
+    etag          TEXT,
+    last_modified TEXT,
+    content_type  TEXT,
+    body          BLOB NOT NULL,
+    fetched_at    INTEGER NOT NULL,
+    next_fetch_at INTEGER
+);
+",
+];
+
+/// Unix timestamp in seconds.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+pub struct NewRecord<'a> {
+    pub hash: &'a str,
+    pub guid: Option<&'a str>,
+    pub title: &'a str,
+    pub link: &'a str,
+    pub channel: &'a str,
+    pub rule: &'a str,
+    pub directory: &'a Path,
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub hash: String,
+    pub guid: Option<String>,
+    pub title: String,
+    pub link: String,
+    pub channel: String,
+    pub rule: String,
+    pub directory: PathBuf,
+    pub file_name: Option<String>,
+    pub added_at: i64,
+    pub seen_at: i64,
+    pub renamed_at: Option<i64>,
+    pub removed_at: Option<i64>,
+    /// when trss gave up waiting for the torrent's metadata to rename it
+    pub rename_failed_at: Option<i64>,
+    /// when trss first saw the download finished
+    pub completed_at: Option<i64>,
+}
+
+impl Record {
+    const COLUMNS: &'static str = "hash, guid, title, link, channel, rule, directory, file_name, added_at, seen_at, renamed_at, removed_at, rename_failed_at, completed_at";
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            hash: row.get(0)?,
+            guid: row.get(1)?,
+            title: row.get(2)?,
+            link: row.get(3)?,
+            channel: row.get(4)?,
+            rule: row.get(5)?,
+            directory: PathBuf::from(row.get::<_, String>(6)?),
+            file_name: row.get(7)?,
+            added_at: row.get(8)?,
+            seen_at: row.get(9)?,
+            renamed_at: row.get(10)?,
+            removed_at: row.get(11)?,
+            rename_failed_at: row.get(12)?,
+            completed_at: row.get(13)?,
+        })
+    }
+}
+
+/// The last successful response of a channel's feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedFeed {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+    /// when the feed was last fetched, or confirmed unchanged
+    pub fetched_at: i64,
+    /// not to be fetched before, from the feed's `<ttl>` or a `Retry-After`
+    pub next_fetch_at: Option<i64>,
+}
+
+impl CachedFeed {
+    const COLUMNS: &'static str =
+        "url, etag, last_modified, content_type, body, fetched_at, next_fetch_at";
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            url: row.get(0)?,
+            etag: row.get(1)?,
+            last_modified: row.get(2)?,
+            content_type: row.get(3)?,
+            body: row.get(4)?,
+            fetched_at: row.get(5)?,
+            next_fetch_at: row.get(6)?,
+        })
+    }
+}
+
+/// On-disk record of every torrent added by trss.
+pub struct History {
+    conn: Mutex<Connection>,
+}
+
+impl History {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HistoryError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, HistoryError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, HistoryError> {
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().expect("history connection poisoned")
+    }
+
+    /// Records an added (or already present) torrent. A torrent that was removed before is tracked
+    /// again, and renamed again as its files are downloaded anew.
+    pub fn record_added(&self, record: NewRecord) -> Result<(), HistoryError> {
+        let now = now();
+
+        self.conn().execute(
+            "INSERT INTO items (hash, guid, title, link, channel, rule, directory, added_at, seen_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)
+             ON CONFLICT (hash) DO UPDATE SET
+                guid = excluded.guid,
+                title = excluded.title,
+                link = excluded.link,
+                channel = excluded.channel,
+                rule = excluded.rule,
+                directory = excluded.directory,
+                seen_at = excluded.seen_at,
+                added_at = CASE WHEN removed_at IS NULL THEN added_at ELSE excluded.added_at END,
+                file_name = CASE WHEN removed_at IS NULL THEN file_name END,
+                renamed_at = CASE WHEN removed_at IS NULL THEN renamed_at END,
+                rename_failed_at = CASE WHEN removed_at IS NULL THEN rename_failed_at END,
+                completed_at = CASE WHEN removed_at IS NULL THEN completed_at END,
+                removed_at = NULL",
+            params![
+                record.hash,
+                record.guid,
+                record.title,
+                record.link,
+                record.channel,
+                record.rule,
+                record.directory.to_string_lossy(),
+                now,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Marks items still present in a feed, so they aren't cleaned up even if adding them failed this run.
+    pub fn record_seen(&self, link: &str) -> Result<(), HistoryError> {
+        self.conn().execute(
+            "UPDATE items SET seen_at = ?2 WHERE link = ?1 AND removed_at IS NULL",
+            params![link, now()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn record_renamed(&self, hash: &str, file_name: &str) -> Result<(), HistoryError> {
+        self.conn().execute(
+            "UPDATE items SET file_name = ?2, renamed_at = ?3 WHERE hash = ?1",
+            params![hash, file_name, now()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Records when a download was first seen finished. Later calls keep the first time.
+    pub fn record_completed(&self, hash: &str) -> Result<(), HistoryError> {
+        self.conn().execute(
+            "UPDATE items SET completed_at = ?2 WHERE hash = ?1 AND completed_at IS NULL",
+            params![hash, now()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Stops retrying the rename of a torrent whose metadata never arrived.
+    pub fn record_rename_failed(&self, hash: &str) -> Result<(), HistoryError> {
+        self.conn().execute(
+            "UPDATE items SET rename_failed_at = ?2 WHERE hash = ?1",
+            params![hash, now()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn record_removed(&self, hash: &str) -> Result<(), HistoryError> {
+        self.conn().execute(
+            "UPDATE items SET removed_at = ?2 WHERE hash = ?1",
+            params![hash, now()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, hash: &str) -> Result<Option<Record>, HistoryError> {
+        let record = self
+            .conn()
+            .query_row(
+                &format!("SELECT {} FROM items WHERE hash = ?1", Record::COLUMNS),
+                params![hash],
+                Record::from_row,
+            )
+            .optional()?;
+
+        Ok(record)
+    }
+
+    /// The latest torrent added from `link` that is still managed.
+    pub fn get_by_link(&self, link: &str) -> Result<Option<Record>, HistoryError> {
+        let record = self
+            .conn()
+            .query_row(
+                &format!(
+                    "SELECT {} FROM items WHERE link = ?1 AND removed_at IS NULL ORDER BY added_at DESC LIMIT 1",
+                    Record::COLUMNS
+                ),
+                params![link],
+                Record::from_row,
+            )
+            .optional()?;
+
+        Ok(record)
+    }
+
+    /// The most recently added torrents, including removed ones.
+    pub fn recent(&self, limit: usize) -> Result<Vec<Record>, HistoryError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM items ORDER BY added_at DESC LIMIT ?1",
+            Record::COLUMNS
+        ))?;
+
+        let records = stmt
+            .query_map(params![limit as i64], Record::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(records)
+    }
+
+    /// Torrents of `channel` that are still managed and waiting to be renamed, oldest first.
+    pub fn pending_renames(&self, channel: &str) -> Result<Vec<Record>, HistoryError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM items
+             WHERE channel = ?1 AND renamed_at IS NULL AND rename_failed_at IS NULL AND removed_at IS NULL
+             ORDER BY added_at",
+            Record::COLUMNS
+        ))?;
+
+        let records = stmt
+            .query_map(params![channel], Record::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(records)
+    }
+
+    /// Torrents of `channel` that are still managed, newest first.
+    pub fn managed(&self, channel: &str) -> Result<Vec<Record>, HistoryError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM items WHERE channel = ?1 AND removed_at IS NULL ORDER BY added_at DESC",
+            Record::COLUMNS
+        ))?;
+
+        let records = stmt
+            .query_map(params![channel], Record::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(records)
+    }
+
+    pub fn cached_feed(&self, url: &str) -> Result<Option<CachedFeed>, HistoryError> {
+        let feed = self
+            .conn()
+            .query_row(
+                &format!("SELECT {} FROM feeds WHERE url = ?1", CachedFeed::COLUMNS),
+                params![url],
+                CachedFeed::from_row,
+            )
+            .optional()?;
+
+        Ok(feed)
+    }
+
+    /// Replaces the cached response of `feed.url`.
+    pub fn record_fetched(&self, feed: &CachedFeed) -> Result<(), HistoryError> {
+        self.conn().execute(
+            "INSERT INTO feeds (url, etag, last_modified, content_type, body, fetched_at, next_fetch_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (url) DO UPDATE SET
+                etag = excluded.etag,
+                last_modified = excluded.last_modified,
+                content_type = excluded.content_type,
+                body = excluded.body,
+                fetched_at = excluded.fetched_at,
+                next_fetch_at = excluded.next_fetch_at",
+            params![
+                feed.url,
+                feed.etag,
+                feed.last_modified,
+                feed.content_type,
+                feed.body,
+                feed.fetched_at,
+                feed.next_fetch_at,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Records that the server said the cached feed of `url` is still current.
+    pub fn record_not_modified(
+        &self,
+        url: &str,
+        next_fetch_at: Option<i64>,
+    ) -> Result<(), HistoryError> {
+        self.conn().execute(
+            "UPDATE feeds SET fetched_at = ?2, next_fetch_at = ?3 WHERE url = ?1",
+            params![url, now(), next_fetch_at],
+        )?;
+
+        Ok(())
+    }
+
+    /// Holds off fetching `url` until `next_fetch_at`. Only feeds fetched before are affected.
+    pub fn record_retry_after(&self, url: &str, next_fetch_at: i64) -> Result<(), HistoryError> {
+        self.conn().execute(
+            "UPDATE feeds SET next_fetch_at = ?2 WHERE url = ?1",
+            params![url, next_fetch_at],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_history() {
+    let history = History::open_in_memory().unwrap();
+
+    let record = NewRecord {
+        hash: "3h7c7x5amcrentmg23ffm3o4eabrmrh5",
+        guid: Some("https://nyaa.si/view/1"),
+        title: "[SubsPlease] Tensei Shitara Slime Datta Ken - 62 (1080p) [0214B01E].mkv",
+        link: "magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH5",
+        channel: "https://nyaa.si/?page=rss",
+        rule: "Tensei Shitara Slime Datta Ken",
+        directory: Path::new("/downloads/Slime/Season 03"),
+    };
+
+    history.record_added(record.clone()).unwrap();
+    assert_eq!(1, history.pending_renames(record.channel).unwrap().len());
+
+    history
+        .record_renamed(record.hash, "Tensei Shitara Slime Datta Ken - S03E14.mkv")
+        .unwrap();
+
+    let saved = history.get(record.hash).unwrap().unwrap();
+
+    assert_eq!(record.link, saved.link);
+    assert_eq!(
+        Some(record.hash),
+        history
+            .get_by_link(record.link)
+            .unwrap()
+            .as_ref()
+            .map(|r| r.hash.as_str())
+    );
+    assert_eq!(
+        Some("Tensei Shitara Slime Datta Ken - S03E14.mkv"),
+        saved.file_name.as_deref()
+    );
+    assert!(saved.renamed_at.is_some());
+    assert!(history.pending_renames(record.channel).unwrap().is_empty());
+
+    history.record_completed(record.hash).unwrap();
+    let completed_at = history.get(record.hash).unwrap().unwrap().completed_at;
+    assert!(completed_at.is_some());
+
+    assert_eq!(1, history.managed(record.channel).unwrap().len());
+
+    assert_eq!(1, history.recent(10).unwrap().len());
+
+    history.record_removed(record.hash).unwrap();
+    assert!(history.managed(record.channel).unwrap().is_empty());
+
+    history.record_added(record.clone()).unwrap();
+    let saved = history.get(record.hash).unwrap().unwrap();
+    assert!(saved.removed_at.is_none());
+    assert!(saved.renamed_at.is_none());
+    assert!(saved.completed_at.is_none());
+
+    history.record_rename_failed(record.hash).unwrap();
+    assert!(history.pending_renames(record.channel).unwrap().is_empty());
+}
+
+#[test]
+fn test_cached_feed() {
+    let history = History::open_in_memory().unwrap();
+
+    let url = "https://nyaa.si/?page=rss";
+    let mut feed = CachedFeed {
+        url: url.to_owned(),
+        etag: Some("\"1\"".to_owned()),
+        last_modified: Some("Tue, 01 Apr 2025 12:00:00 GMT".to_owned()),
+        content_type: Some("application/rss+xml".to_owned()),
+        body: b"<rss version=\"2.0\"></rss>".to_vec(),
+        fetched_at: 1,
+        next_fetch_at: None,
+    };
+
+    assert!(history.cached_feed(url).unwrap().is_none());
+
+    history.record_retry_after(url, 10).unwrap();
+    assert!(history.cached_feed(url).unwrap().is_none());
+
+    history.record_fetched(&feed).unwrap();
+    assert_eq!(Some(&feed), history.cached_feed(url).unwrap().as_ref());
+
+    feed.etag = Some("\"2\"".to_owned());
+    history.record_fetched(&feed).unwrap();
+    assert_eq!(Some(&feed), history.cached_feed(url).unwrap().as_ref());
+
+    history.record_not_modified(url, Some(20)).unwrap();
+    let saved = history.cached_feed(url).unwrap().unwrap();
+    assert_eq!(Some(20), saved.next_fetch_at);
+    assert!(saved.fetched_at > 1);
+    assert_eq!(feed.body, saved.body);
+
+    history.record_retry_after(url, 30).unwrap();
+    assert_eq!(
+        Some(30),
+        history.cached_feed(url).unwrap().unwrap().next_fetch_at
+    );
+}
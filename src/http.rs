@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use reqwest::{header::COOKIE, RequestBuilder, Response};
+use tokio::time::sleep;
+use url::Url;
+
+use crate::config::{ChannelConfig, Config};
+
+/// Delay before the first retry, doubled after each.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// HTTP client for feeds, shared by every channel.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    retries: u32,
+}
+
+impl HttpClient {
+    pub fn new(config: &Config) -> Result<Self, reqwest::Error> {
+        let mut client = reqwest::Client::builder()
+            .user_agent(&config.feed_user_agent)
+            .connect_timeout(Duration::from_secs(config.feed_connect_timeout))
+            .read_timeout(Duration::from_secs(config.feed_read_timeout));
+
+        if let Some(proxy) = &config.feed_proxy {
+            client = client.proxy(reqwest::Proxy::all(proxy.as_str())?);
+        }
+
+        Ok(Self {
+            client: client.build()?,
+            retries: config.feed_retries,
+        })
+    }
+
+    /// A GET of the channel's feed, with its headers, cookies and passkey.
+    pub fn get(&self, channel_config: &ChannelConfig) -> RequestBuilder {
+        let mut req = self.client.get(channel_config.request_url());
+
+        for (name, value) in &channel_config.headers {
+            req = req.header(name, value.expose());
+        }
+
+        if let Some(cookies) = channel_config.cookie_header() {
+            req = req.header(COOKIE, cookies);
+        }
+
+        req
+    }
+
+    /// A GET of `url`, without any channel's headers.
+    pub fn get_url(&self, url: Url) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// Sends `req`, retrying with exponential backoff after a 5xx or network error.
+    ///
+    /// Once out of retries, the last response or error is returned, [`without_url`].
+    pub async fn send(&self, req: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+
+        loop {
+            let res = req
+                .try_clone()
+                .expect("requests without a streaming body can be cloned")
+                .send()
+                .await
+                .map_err(without_url);
+
+            let retry = match &res {
+                Ok(res) => res.status().is_server_error(),
+                Err(err) => err.is_connect() || err.is_timeout() || err.is_request(),
+            };
+
+            if !retry || attempt >= self.retries {
+                return res;
+            }
+
+            sleep(RETRY_DELAY.saturating_mul(2u32.saturating_pow(attempt))).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Leaves out the URL of `err`. Feed URLs carry passkeys and webhook URLs tokens, and errors end
+/// up in logs, reports and notifications.
+pub fn without_url(err: reqwest::Error) -> reqwest::Error {
+    err.without_url()
+}
+
+#[test]
+fn test_get() {
+    let config = Config::from_vars(|key| match key {
+        "CHANNELS_CONFIG_URL" => Some("channels.yaml".to_owned()),
+        "TRANSMISSION_URL" => Some("http://localhost:9091/transmission/rpc".to_owned()),
+        _ => None,
+    })
+    .unwrap();
+
+    let channels = yaml_serde::from_str::<Vec<ChannelConfig>>(
+        "
+- url: https://tracker.example.com/rss
+  directory: /downloads
+  headers:
+    X-Api-Key: abc
+  cookies:
+    uid: '1234'
+  passkey: key
+  rules: []
+",
+    )
+    .unwrap();
+
+    let req = HttpClient::new(&config)
+        .unwrap()
+        .get(&channels[0])
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        "https://tracker.example.com/rss?passkey=key",
+        req.url().as_str()
+    );
+    assert_eq!("abc", req.headers()["x-api-key"]);
+    assert_eq!("uid=1234", req.headers()[COOKIE]);
+}
+
+#[tokio::test]
+async fn test_send_error_without_passkey() {
+    let addr = crate::mock::unused_addr();
+
+    let config = Config::from_vars(|key| match key {
+        "CHANNELS_CONFIG_URL" => Some("channels.yaml".to_owned()),
+        "TRANSMISSION_URL" => Some("http://localhost:9091/transmission/rpc".to_owned()),
+        "FEED_RETRIES" => Some("0".to_owned()),
+        _ => None,
+    })
+    .unwrap();
+
+    let channels = yaml_serde::from_str::<Vec<ChannelConfig>>(&format!(
+        "
+- url: http://{addr}/rss
+  directory: /downloads
+  passkey: s3cret
+  rules: []
+"
+    ))
+    .unwrap();
+
+    let http = HttpClient::new(&config).unwrap();
+
+    let err = http.send(http.get(&channels[0])).await.unwrap_err();
+
+    assert!(err.is_connect());
+    assert!(!err.to_string().contains("s3cret"));
+    assert!(!format!("{err:?}").contains("s3cret"));
+}
@@ -1,4 +1,19 @@
 #[cfg(feature = "anissia")]
 pub mod anissia;
+pub mod client;
 pub mod config;
+pub mod feed;
+pub mod history;
+pub mod http;
+pub mod metrics;
+#[cfg(test)]
+mod mock;
+pub mod notify;
+pub mod plan;
+pub mod qbittorrent;
+pub mod rename;
+pub mod report;
+pub mod retention;
 pub mod rule;
+pub mod transmission;
+pub mod validate;
@@ -1,511 +1,2405 @@
 use std::{
-    path::{Path, PathBuf},
-    time::Duration,
+    collections::{HashMap, HashSet},
+    future::Future,
+    path::Path,
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
-use futures::{stream, StreamExt};
-use rss::{Channel, Item};
-use tokio::time::sleep;
-use transmission_rpc::{
-    types::{
-        Id, SessionSetArgs, Torrent, TorrentAction, TorrentAddArgs, TorrentAddedOrDuplicate,
-        TorrentGetField, TorrentStatus,
+use clap::{Args, Parser, Subcommand};
+use futures::{future, stream, StreamExt};
+use reqwest::{
+    header::{
+        HeaderName, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        RETRY_AFTER,
     },
-    TransClient,
+    StatusCode,
 };
+use tokio::{
+    sync::{watch, Semaphore},
+    task::JoinSet,
+    time::sleep,
+};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use tracing_subscriber::EnvFilter;
 use transmission_rss::{
-    config::{ChannelConfig, Config},
+    client::{
+        self, AddTorrent, Added, ClientError, DownloadClient, SessionSettings, Torrent,
+        TorrentStatus,
+    },
+    config::{ChannelConfig, ChannelsSource, Config, ConfigProblem, LogFormat, SeedingPolicy},
+    feed::{Feed, FeedError, FeedItem, TorrentLink},
+    history::{self, CachedFeed, History, HistoryError, NewRecord, Record},
+    http::{without_url, HttpClient},
+    metrics::{self, METRICS},
+    notify::{Event, Notifier},
+    plan::{table, Plan, PlannedAdd, PlannedRemoval, PlannedRename},
+    rename::{
+        name_captured, BatchRename, Captured, FileOutcome, UnparseablePolicy, NEEDS_REVIEW_LABEL,
+    },
+    report::{
+        AddOutcome, AddReport, ChannelReport, FetchResult, MatchedItem, Recorder, RemovedTorrent,
+        RenameOutcome, RenameReport, TorrentReport,
+    },
+    retention::{self, Candidate, Reason},
     rule::Rule,
 };
 use trname::trname;
-use url::Url;
+
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "mock.rs"]
+mod mock;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ChannelParseError {
     #[error("reqwest: {0}")]
-    Reqwest(#[from] reqwest::Error),
-    #[error("rss: {0}")]
-    Rss(#[from] rss::Error),
-}
-
-async fn parse_channel(channel_config: &ChannelConfig) -> Result<Channel, ChannelParseError> {
-    let buf = reqwest::get(&channel_config.url).await?.bytes().await?;
-    let channel = rss::Channel::read_from(&buf[..])?;
-
-    Ok(channel)
-}
-
-// fn parse_hash(magnet: &str) -> Option<&str> {
-//     if magnet.starts_with("magnet:?xt=urn:btih:") {
-//         Some(
-//             magnet["magnet:?xt=urn:btih:".len()..]
-//                 .splitn(2, '&')
-//                 .next()
-//                 .unwrap(),
-//         )
-//     } else {
-//         None
-//     }
-// }
-
-// #[test]
-// fn test_parse_hash() {
-//     let magnet = "magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH5&dn=%5BSubsPlease%5D%20Tensei%20Shitara%20Slime%20Datta%20Ken%20-%2062%20%281080p%29%20%5B0214B01E%5D.mkv&xl=1497945704&tr=http%3A%2F%2Fnyaa.tracker.wf%3A7777%2Fannounce&tr=udp%3A%2F%2Ftracker.coppersurfer.tk%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker.opentrackr.org%3A1337%2Fannounce&tr=udp%3A%2F%2F9.rarbg.to%3A2710%2Fannounce&tr=udp%3A%2F%2F9.rarbg.me%3A2710%2Fannounce&tr=udp%3A%2F%2Ftracker.leechers-paradise.org%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker.internetwarriors.net%3A1337%2Fannounce&tr=udp%3A%2F%2Ftracker.cyberia.is%3A6969%2Fannounce&tr=udp%3A%2F%2Fexodus.desync.com%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker3.itzmx.com%3A6961%2Fannounce&tr=udp%3A%2F%2Ftracker.torrent.eu.org%3A451%2Fannounce&tr=udp%3A%2F%2Ftracker.tiny-vps.com%3A6969%2Fannounce&tr=udp%3A%2F%2Fretracker.lanta-net.ru%3A2710%2Fannounce&tr=http%3A%2F%2Fopen.acgnxtracker.com%3A80%2Fannounce&tr=wss%3A%2F%2Ftracker.openwebtorrent.com";
-
-//     assert_eq!(
-//         "3H7C7X5AMCRENTMG23FFM3O4EABRMRH5",
-//         parse_hash(magnet).unwrap()
-//     );
-
-//     let magnet = "magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH5&";
-
-//     assert_eq!(
-//         "3H7C7X5AMCRENTMG23FFM3O4EABRMRH5",
-//         parse_hash(magnet).unwrap()
-//     );
-
-//     let magnet = "magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH5";
-
-//     assert_eq!(
-//         "3H7C7X5AMCRENTMG23FFM3O4EABRMRH5",
-//         parse_hash(magnet).unwrap()
-//     );
-
-//     let anything = "https://google.com";
-
-//     assert!(parse_hash(anything).is_none());
-// }
-
-async fn get_torrents(
-    transmission: &mut TransClient,
-) -> transmission_rpc::types::Result<Vec<Torrent>> {
-    let res = transmission
-        .torrent_get(
-            Some(vec![
-                TorrentGetField::Id,
-                TorrentGetField::Name,
-                TorrentGetField::HashString,
-                TorrentGetField::Labels,
-            ]),
-            None,
-        )
-        .await?;
+    Reqwest(reqwest::Error),
+    #[error("{0}")]
+    Feed(#[from] FeedError),
+    #[error("HTTP {status}")]
+    Status {
+        status: StatusCode,
+        /// unix timestamp from `Retry-After`
+        retry_after: Option<i64>,
+    },
+}
 
-    Ok(res.arguments.torrents)
+impl From<reqwest::Error> for ChannelParseError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Reqwest(without_url(err))
+    }
 }
 
-async fn get_torrent(
-    transmission: &mut TransClient,
-    hash: &str,
-) -> transmission_rpc::types::Result<Option<Torrent>> {
-    let res = transmission
-        .torrent_get(
-            Some(vec![
-                TorrentGetField::Id,
-                TorrentGetField::Name,
-                TorrentGetField::HashString,
-                TorrentGetField::Status,
-                TorrentGetField::Labels,
-                TorrentGetField::FileCount,
-            ]),
-            Some(vec![Id::Hash(hash.to_owned())]),
-        )
-        .await?;
+/// History failures are logged, never fatal to a run.
+fn log_history_error(err: &HistoryError) {
+    error!(error = %err, "Can't access the history");
+}
+
+/// Sends the digest of `events` if notifications are configured. Failures are only logged.
+async fn notify(notifier: Option<&Notifier>, events: &[Event]) {
+    let Some(notifier) = notifier else {
+        return;
+    };
 
-    Ok(res.arguments.torrents.into_iter().next())
+    if let Err(err) = notifier.send(events).await {
+        error!(error = %err, events = events.len(), "Can't send notification");
+    }
 }
 
-#[tokio::test]
-async fn test_get_torrent() {
-    let mut transmission = TransClient::new(
-        "http://192.168.1.21:32091/transmission/rpc"
-            .parse()
-            .expect("can't parse transmission url"),
-    );
+/// The notifier if notifications are configured. One that can't be set up is logged and left out,
+/// as notifications never fail a run.
+fn notifier(config: &Config) -> Option<Notifier> {
+    Notifier::new(config)
+        .inspect_err(|err| error!(error = %err, "Can't set up notifications"))
+        .ok()
+        .flatten()
+}
 
-    let res = transmission
-        .torrent_get(
-            Some(vec![
-                TorrentGetField::Id,
-                TorrentGetField::Name,
-                TorrentGetField::HashString,
-                TorrentGetField::Status,
-                TorrentGetField::Labels,
-                TorrentGetField::Error,
-                TorrentGetField::ErrorString,
-                TorrentGetField::DoneDate,
-                TorrentGetField::FileStats,
-                TorrentGetField::FileCount,
-                TorrentGetField::Files,
-            ]),
-            // Some(vec![Id::Hash(
-            //     "457be58a312d7a3881783b014cbf766e370c0598".to_owned(),
-            // )]),
-            None,
-        )
-        .await
-        .unwrap();
+/// Counts an item or torrent of `channel` in the `trss_items_total` metric.
+fn count(channel: &str, outcome: &str) {
+    METRICS.items.with_label_values(&[channel, outcome]).inc();
+}
+
+/// Awaits a request to the download client, timing it in the `trss_client_request_seconds` metric.
+async fn rpc<T>(method: &str, call: impl Future<Output = T>) -> T {
+    let _timer = METRICS
+        .request_seconds
+        .with_label_values(&[method])
+        .start_timer();
 
-    println!("{res:#?}");
+    call.await
 }
 
-const BOT_LABEL: &str = "managed:transmission-rss";
+fn header(res: &reqwest::Response, name: HeaderName) -> Option<String> {
+    res.headers()
+        .get(name)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.to_owned())
+}
 
-fn has_label(labels: Option<&[String]>, x: &str) -> bool {
-    labels.is_some_and(|labels| labels.iter().any(|label| label == x))
-}
-
-async fn add_torrent(
-    transmission: &mut TransClient,
-    link: &str,
-    download_dir: &Path,
-) -> transmission_rpc::types::Result<TorrentAddedOrDuplicate> {
-    let mut res = transmission
-        .torrent_add(TorrentAddArgs {
-            filename: Some(link.to_owned()),
-            labels: Some(vec![BOT_LABEL.to_owned()]),
-            download_dir: download_dir.to_str().map(|x| x.to_owned()),
-            ..Default::default()
-        })
-        .await?;
+/// Parses `Retry-After`, either seconds or an HTTP date, into a unix timestamp.
+fn parse_retry_after(x: &str, now: i64) -> Option<i64> {
+    let x = x.trim();
+
+    match x.parse::<u64>() {
+        Ok(secs) => Some(now + secs as i64),
+        Err(_) => httpdate::parse_http_date(x)
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|x| x.as_secs() as i64),
+    }
+}
+
+#[test]
+fn test_parse_retry_after() {
+    assert_eq!(Some(1120), parse_retry_after("120", 1000));
+    assert_eq!(
+        Some(1743508800),
+        parse_retry_after("Tue, 01 Apr 2025 12:00:00 GMT", 1000)
+    );
+    assert_eq!(None, parse_retry_after("soon", 1000));
+}
 
-    match &mut res.arguments {
-        TorrentAddedOrDuplicate::TorrentDuplicate(torrent) => {
-            *torrent = get_torrent(transmission, torrent.hash_string.as_deref().unwrap())
-                .await?
-                .unwrap();
+/// Requests a channel's feed, conditionally on the validators of `cached`.
+///
+/// `304 Not Modified` is returned like a success; other statuses are errors.
+async fn request_channel(
+    http: &HttpClient,
+    channel_config: &ChannelConfig,
+    cached: Option<&CachedFeed>,
+) -> Result<reqwest::Response, ChannelParseError> {
+    let mut req = http.get(channel_config);
+
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
         }
-        TorrentAddedOrDuplicate::TorrentAdded(torrent) => {
-            *torrent = get_torrent(transmission, torrent.hash_string.as_deref().unwrap())
-                .await?
-                .unwrap();
+    }
+
+    let res = http.send(req).await?;
+    let status = res.status();
+
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(res);
+    }
+
+    let retry_after = header(&res, RETRY_AFTER).and_then(|x| parse_retry_after(&x, history::now()));
+
+    Err(ChannelParseError::Status {
+        status,
+        retry_after,
+    })
+}
+
+/// Fetches a channel's RSS, Atom or JSON Feed, bypassing the cache.
+async fn parse_channel(
+    http: &HttpClient,
+    channel_config: &ChannelConfig,
+) -> Result<Feed, ChannelParseError> {
+    let res = request_channel(http, channel_config, None).await?;
+
+    let content_type = header(&res, CONTENT_TYPE);
+
+    let buf = res.bytes().await?;
+    let feed = Feed::parse(content_type.as_deref(), &buf)?;
+
+    Ok(feed)
+}
+
+/// A channel's feed, fetched or from the cache.
+#[derive(Debug, Clone)]
+struct Fetched {
+    feed: Feed,
+    /// whether the feed can have new items, i.e. it wasn't served from the cache
+    modified: bool,
+    /// unix timestamp before which the feed isn't fetched again
+    next_fetch_at: Option<i64>,
+}
+
+fn parse_cached(cached: &CachedFeed) -> Result<Feed, FeedError> {
+    Feed::parse(cached.content_type.as_deref(), &cached.body)
+}
+
+/// Fetches a channel's feed, caching the response in the history.
+///
+/// The cached feed is used as is while its `<ttl>` or a `Retry-After` hasn't passed, and when the
+/// server answers `304 Not Modified` to the cached `ETag`/`Last-Modified`.
+async fn fetch_channel(
+    http: &HttpClient,
+    history: &History,
+    channel_config: &ChannelConfig,
+) -> Result<Fetched, ChannelParseError> {
+    let url = channel_config.url.as_str();
+    let now = history::now();
+
+    let cached = history
+        .cached_feed(url)
+        .inspect_err(log_history_error)
+        .ok()
+        .flatten();
+
+    if let Some(cached) = &cached {
+        if cached.next_fetch_at.is_some_and(|x| x > now) {
+            return Ok(Fetched {
+                feed: parse_cached(cached)?,
+                modified: false,
+                next_fetch_at: cached.next_fetch_at,
+            });
         }
-        TorrentAddedOrDuplicate::Error => {
-            eprintln!("{}", res.result);
+    }
+
+    let res = match request_channel(http, channel_config, cached.as_ref()).await {
+        Ok(r) => r,
+        Err(err) => {
+            if let ChannelParseError::Status {
+                retry_after: Some(retry_after),
+                ..
+            } = err
+            {
+                history
+                    .record_retry_after(url, retry_after)
+                    .inspect_err(log_history_error)
+                    .ok();
+            }
+
+            return Err(err);
         }
+    };
+
+    let next_fetch_at = |feed: &Feed| feed.ttl.map(|ttl| now + ttl as i64 * 60);
+
+    if let (StatusCode::NOT_MODIFIED, Some(cached)) = (res.status(), &cached) {
+        let feed = parse_cached(cached)?;
+        let next_fetch_at = next_fetch_at(&feed);
+
+        history
+            .record_not_modified(url, next_fetch_at)
+            .inspect_err(log_history_error)
+            .ok();
+
+        return Ok(Fetched {
+            feed,
+            modified: false,
+            next_fetch_at,
+        });
     }
 
-    Ok(res.arguments)
+    let etag = header(&res, ETAG);
+    let last_modified = header(&res, LAST_MODIFIED);
+    let content_type = header(&res, CONTENT_TYPE);
+
+    let body = res.bytes().await?.to_vec();
+    let feed = Feed::parse(content_type.as_deref(), &body)?;
+    let next_fetch_at = next_fetch_at(&feed);
+
+    history
+        .record_fetched(&CachedFeed {
+            url: url.to_owned(),
+            etag,
+            last_modified,
+            content_type,
+            body,
+            fetched_at: now,
+            next_fetch_at,
+        })
+        .inspect_err(log_history_error)
+        .ok();
+
+    Ok(Fetched {
+        feed,
+        modified: true,
+        next_fetch_at,
+    })
 }
 
-#[tokio::test]
-#[ignore]
-async fn test_add_torrent() {
-    let link = "magnet:?xt=urn:btih:SEFD6A5N67C2CJ3NDJI74AP6CZXTCFSZ&dn=%5BSubsPlease%5D%20Katsute%20Mahou%20Shoujo%20to%20Aku%20wa%20Tekitai%20shiteita%20-%2002%20%281080p%29%20%5BC2A5EFC3%5D.mkv&xl=767183596&tr=http%3A%2F%2Fnyaa.tracker.wf%3A7777%2Fannounce&tr=udp%3A%2F%2Ftracker.coppersurfer.tk%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker.opentrackr.org%3A1337%2Fannounce&tr=udp%3A%2F%2F9.rarbg.to%3A2710%2Fannounce&tr=udp%3A%2F%2F9.rarbg.me%3A2710%2Fannounce&tr=udp%3A%2F%2Ftracker.leechers-paradise.org%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker.internetwarriors.net%3A1337%2Fannounce&tr=udp%3A%2F%2Ftracker.cyberia.is%3A6969%2Fannounce&tr=udp%3A%2F%2Fexodus.desync.com%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker3.itzmx.com%3A6961%2Fannounce&tr=udp%3A%2F%2Ftracker.torrent.eu.org%3A451%2Fannounce&tr=udp%3A%2F%2Ftracker.tiny-vps.com%3A6969%2Fannounce&tr=udp%3A%2F%2Fretracker.lanta-net.ru%3A2710%2Fannounce&tr=http%3A%2F%2Fopen.acgnxtracker.com%3A80%2Fannounce&tr=wss%3A%2F%2Ftracker.openwebtorrent.com";
-
-    let mut transmission = TransClient::new(
-        "http://192.168.1.21:32091/transmission/rpc"
-            .parse()
-            .expect("can't parse transmission url"),
+/// The last successfully fetched feed of a channel, for cleaning up when fetching it fails.
+fn cached_channel(
+    history: &History,
+    channel_config: &ChannelConfig,
+    report: &Recorder,
+) -> Option<Fetched> {
+    let cached = history
+        .cached_feed(&channel_config.url)
+        .inspect_err(log_history_error)
+        .ok()
+        .flatten()?;
+
+    let feed = parse_cached(&cached)
+        .inspect_err(
+            |err| warn!(channel = %channel_config.url, error = %err, "Can't parse the cached feed"),
+        )
+        .ok()?;
+
+    METRICS
+        .fetches
+        .with_label_values(&[channel_config.url.as_str(), "cached"])
+        .inc();
+
+    report.record(|report| {
+        if let Some(channel) = report.channel_mut(&channel_config.url) {
+            channel.result = FetchResult::Cached;
+            channel.items = feed.items.len();
+        }
+    });
+
+    info!(
+        action = "cached",
+        channel = %channel_config.url,
+        fetched_at = cached.fetched_at,
+        "Using the cached feed"
+    );
+
+    Some(Fetched {
+        feed,
+        modified: false,
+        next_fetch_at: None,
+    })
+}
+
+const BOT_LABEL: &str = "managed:transmission-rss";
+
+/// Outcome of one attempt to rename a torrent.
+enum Renamed {
+    /// metadata isn't there yet, or the client refused the rename
+    Pending,
+    /// new name of the torrent's file, or of its folder for multi-file torrents
+    Done(String),
+    /// trname couldn't name the torrent, so the policy was applied to it (still called this)
+    Unparseable(UnparseablePolicy, String),
+}
+
+/// New name of `file_name`, from the `episode` and `season` groups `rule` captured from `title`,
+/// or else from trname.
+fn new_name(directory: &Path, file_name: &str, rule: &Rule, title: &str) -> Option<String> {
+    rule.captures(title)
+        .and_then(|captures| {
+            name_captured(
+                directory,
+                file_name,
+                Captured::from(&captures),
+                rule.starts_episode_at,
+            )
+        })
+        .or_else(|| trname(directory, file_name, rule.starts_episode_at))
+}
+
+#[test]
+fn test_new_name() {
+    let directory = Path::new("/downloads/Dandadan/Season 02");
+    let title = "[SubsPlease] Dandadan - 13 (1080p) [0214B01E].mkv";
+
+    let rule = yaml_serde::from_str::<Rule>(
+        "
+match: '^\\[SubsPlease\\] Dandadan - (?P<episode>\\d+) \\(1080p\\)'
+regex: true
+directory: Dandadan/Season 02
+",
+    )
+    .unwrap();
+
+    assert_eq!(
+        Some("Dandadan - S02E13.mkv"),
+        new_name(directory, title, &rule, title).as_deref()
     );
 
-    let res = add_torrent(
-        &mut transmission,
-        link,
-        &PathBuf::from(
-            "/downloads/Shows (current)/Katsute Mahou Shoujo to Aku wa Tekitai shiteita/Season 01",
-        ),
+    // without the groups, trname names it
+    let rule = yaml_serde::from_str::<Rule>(
+        "
+match: Dandadan
+directory: Dandadan/Season 02
+",
     )
-    .await
     .unwrap();
 
-    println!("{res:#?}");
+    assert_eq!(
+        trname(directory, title, 1),
+        new_name(directory, title, &rule, title)
+    );
 }
 
+/// Renames `torrent`, added for the item `title`.
+///
+/// A regex rule's captured episode names a single file; the files of a multi-file torrent are
+/// each named by trname, as they're different episodes.
 async fn rename_torrent(
-    transmission: &mut TransClient,
-    hash: &str,
-    download_dir: &PathBuf,
-    starts_episode_at: isize,
-) -> transmission_rpc::types::Result<Option<String>> {
-    let Some(torrent) = get_torrent(transmission, hash).await? else {
-        return Ok(None);
-    };
-
-    if torrent.file_count.unwrap() == 1 {
-        let old_file_name = torrent.name.clone().unwrap();
+    client: &dyn DownloadClient,
+    torrent: &Torrent,
+    channel_config: &ChannelConfig,
+    matched: &Rule,
+    title: &str,
+) -> Result<Renamed, ClientError> {
+    if !torrent.metadata_complete {
+        return Ok(Renamed::Pending);
+    }
 
-        match trname(download_dir, &old_file_name, starts_episode_at) {
-            Some(new_file_name) => {
-                let res = transmission
-                    .torrent_rename_path(
-                        vec![Id::Hash(hash.to_owned())],
-                        old_file_name,
-                        new_file_name.clone(),
+    let hash = torrent.hash.as_str();
+    let download_dir = matched.directory(&channel_config.directory);
+    let policy = channel_config.unparseable(matched);
+
+    match torrent.files.len() {
+        // metadata isn't there yet
+        0 => {}
+        1 => {
+            let old_file_name = torrent.name.clone();
+
+            match new_name(&download_dir, &old_file_name, matched, title) {
+                Some(new_file_name) => {
+                    let renamed = rpc(
+                        "rename_path",
+                        client.rename_path(hash, &old_file_name, &new_file_name),
                     )
                     .await?;
 
-                if res.result == "success" {
-                    return Ok(Some(new_file_name));
+                    if renamed {
+                        return Ok(Renamed::Done(new_file_name));
+                    }
+                }
+                None => {
+                    apply_unparseable(client, torrent, policy, &channel_config.quarantine_dir)
+                        .await?;
+
+                    return Ok(Renamed::Unparseable(policy, old_file_name));
                 }
             }
-            None => {
-                let _res = transmission
-                    .torrent_remove(vec![Id::Hash(hash.to_owned())], true)
-                    .await?;
+        }
+        _ => {
+            let batch = BatchRename::new(
+                &download_dir,
+                torrent.files.iter().map(|x| x.as_str()),
+                |name| trname(&download_dir, name, matched.starts_episode_at),
+            );
+
+            if !batch.renamed_any() {
+                apply_unparseable(client, torrent, policy, &channel_config.quarantine_dir).await?;
+
+                return Ok(Renamed::Unparseable(policy, batch.folder));
             }
+
+            return rename_batch(client, hash, &batch).await.map(Renamed::Done);
         }
     }
 
-    Ok(None)
+    Ok(Renamed::Pending)
 }
 
-async fn run() {
-    let config = Config::new();
-    let channels_config: Vec<ChannelConfig> = yaml_serde::from_slice(
-        &reqwest::get(&config.channels_config_url)
-            .await
-            .expect("can't get channels configuration")
-            .bytes()
-            .await
-            .unwrap(),
-    )
-    .expect("can't deserialize channels configuration");
-
-    let transmission_url = config
-        .transmission_url
-        .parse::<Url>()
-        .expect("can't parse transmission url");
-
-    let mut transmission = TransClient::new(transmission_url.clone());
-
-    let transmission_config = SessionSetArgs {
-        download_dir: config.download_dir,
-        speed_limit_up_enabled: config.speed_limit_up.is_some().then_some(true),
-        speed_limit_up: config.speed_limit_up,
-        speed_limit_down_enabled: config.speed_limit_down.is_some().then_some(true),
-        speed_limit_down: config.speed_limit_down,
-        download_queue_enabled: config.download_queue_size.is_some().then_some(true),
-        download_queue_size: config.download_queue_size,
-        seed_queue_enabled: config.seed_queue_size.is_some().then_some(true),
-        seed_queue_size: config.seed_queue_size,
-        ..Default::default()
-    };
+/// Handles a torrent trname can't name according to `policy`.
+async fn apply_unparseable(
+    client: &dyn DownloadClient,
+    torrent: &Torrent,
+    policy: UnparseablePolicy,
+    quarantine_dir: &Path,
+) -> Result<(), ClientError> {
+    let hash = torrent.hash.as_str();
+    let name = torrent.name.as_str();
+
+    match policy {
+        UnparseablePolicy::Delete => {
+            rpc("remove", client.remove(&[hash.to_owned()], true)).await?;
+
+            info!(
+                action = "deleted",
+                hash, name, "Deleted unparseable torrent"
+            );
+
+            return Ok(());
+        }
+        UnparseablePolicy::KeepOriginalName => {
+            info!(action = "kept", hash, name, "Kept unparseable torrent");
+
+            return Ok(());
+        }
+        UnparseablePolicy::MoveToQuarantineDir => {
+            rpc("set_location", client.set_location(hash, quarantine_dir)).await?;
+
+            info!(
+                action = "quarantined",
+                hash,
+                name,
+                directory = %quarantine_dir.display(),
+                "Quarantined unparseable torrent"
+            );
+        }
+        UnparseablePolicy::LabelForReview => {}
+    }
 
-    println!("{:#?}", transmission_config);
+    let mut labels = torrent.labels.clone();
 
-    transmission
-        .session_set(transmission_config)
-        .await
-        .expect("can't set transmission configuration");
-
-    pub fn collect_items<'a>(
-        channels: impl Iterator<Item = &'a mut (Channel, ChannelConfig)>,
-    ) -> Vec<(PathBuf, &'a Rule, &'a mut Item)> {
-        let mut items = Vec::new();
-
-        for (channel, channel_config) in channels {
-            for item in channel.items_mut() {
-                let title = item.title().unwrap_or_default();
-
-                if channel_config
-                    .excludes
-                    .iter()
-                    .any(|ex| title.contains(ex.as_str()))
-                {
-                    continue;
+    if !torrent.has_label(NEEDS_REVIEW_LABEL) {
+        labels.push(NEEDS_REVIEW_LABEL.to_owned());
+    }
+
+    rpc("set_labels", client.set_labels(hash, &labels)).await?;
+
+    info!(
+        action = "labeled",
+        hash,
+        name,
+        label = NEEDS_REVIEW_LABEL,
+        "Needs review"
+    );
+
+    Ok(())
+}
+
+/// Renames every video of a multi-file torrent and its folder, and skips everything else.
+///
+/// Returns the name of the torrent's folder afterwards.
+async fn rename_batch(
+    client: &dyn DownloadClient,
+    hash: &str,
+    batch: &BatchRename,
+) -> Result<String, ClientError> {
+    let unwanted = batch.unwanted();
+
+    if !unwanted.is_empty() {
+        rpc("skip_files", client.skip_files(hash, &unwanted)).await?;
+    }
+
+    for file in &batch.files {
+        match &file.outcome {
+            FileOutcome::Renamed(new_file_name) => {
+                let renamed = rpc(
+                    "rename_path",
+                    client.rename_path(hash, &file.path, new_file_name),
+                )
+                .await?;
+
+                if renamed {
+                    info!(action = "renamed", hash, path = %file.path, new_name = %new_file_name, "Renamed");
+                } else {
+                    warn!(hash, path = %file.path, "Rename failed");
                 }
+            }
+            FileOutcome::Unparseable => {
+                warn!(action = "unparseable", hash, path = %file.path, "Unparseable")
+            }
+            FileOutcome::Unwanted => {
+                debug!(action = "unwanted", hash, path = %file.path, "Unwanted")
+            }
+        }
+    }
+
+    if let Some(new_folder) = &batch.new_folder {
+        let renamed = rpc(
+            "rename_path",
+            client.rename_path(hash, &batch.folder, new_folder),
+        )
+        .await?;
+
+        if renamed {
+            info!(action = "renamed", hash, path = %batch.folder, new_name = %new_folder, "Renamed");
+            return Ok(new_folder.clone());
+        }
+    }
 
-                let matched = channel_config.rules.iter().find(|rule| rule.test(title));
+    Ok(batch.folder.clone())
+}
 
-                let Some(matched) = matched else {
+/// An item a rule matched, with its channel and torrent link.
+type Matched<'a> = (&'a ChannelConfig, &'a Rule, &'a FeedItem, TorrentLink);
+
+fn collect_items<'a>(
+    channels: impl Iterator<Item = (&'a Feed, &'a ChannelConfig)>,
+) -> Vec<Matched<'a>> {
+    let mut items = Vec::new();
+
+    for (channel, channel_config) in channels {
+        for item in &channel.items {
+            let title = item.title.as_str();
+
+            let matched = match channel_config.find_rule(title) {
+                Ok(r) => r,
+                Err(rejected) => {
+                    if let Some((rule, rejection)) = rejected {
+                        count(&channel_config.url, "skipped");
+
+                        info!(
+                            action = "skipped",
+                            channel = %channel_config.url,
+                            rule = %rule.r#match,
+                            title,
+                            reason = %rejection,
+                            "Skipped"
+                        );
+                    }
                     continue;
-                };
+                }
+            };
+
+            let Some(link) = item.torrent_link() else {
+                count(&channel_config.url, "skipped");
+
+                warn!(
+                    action = "skipped",
+                    channel = %channel_config.url,
+                    rule = %matched.r#match,
+                    title,
+                    reason = "no torrent link",
+                    "Skipped"
+                );
+                continue;
+            };
+
+            count(&channel_config.url, "matched");
+
+            info!(
+                action = "matched",
+                channel = %channel_config.url,
+                rule = %matched.r#match,
+                title,
+                hash = link.hash.as_deref(),
+                "Matched"
+            );
+
+            items.push((channel_config, matched, item, link));
+
+            // return items;
+        }
+    }
 
-                items.push((channel_config.directory.clone(), matched, item));
+    items
+}
 
-                println!("Matched {}", matched.r#match);
+async fn load_channels(config: &Config, http: &HttpClient) -> Vec<ChannelConfig> {
+    match ChannelsSource::parse(&config.channels_config_url)
+        .load(http)
+        .await
+    {
+        Ok(r) => r,
+        Err(err) => {
+            error!(error = %err, "Can't load the channels configuration");
+            std::process::exit(EXIT_CONFIG.into());
+        }
+    }
+}
 
-                // return items;
-            }
+fn http_client(config: &Config) -> HttpClient {
+    match HttpClient::new(config) {
+        Ok(r) => r,
+        Err(err) => {
+            error!(error = %err, "Can't set up the feed client");
+            std::process::exit(EXIT_CONFIG.into());
         }
+    }
+}
 
-        items
+fn download_client(config: &Config) -> Box<dyn DownloadClient> {
+    match client::from_config(config) {
+        Ok(r) => r,
+        Err(err) => {
+            error!(error = %err, "Can't set up the download client");
+            std::process::exit(EXIT_CONFIG.into());
+        }
     }
+}
 
-    let mut channels = stream::iter(channels_config.into_iter())
-        .map(|channel_config| async {
-            (
-                parse_channel(&channel_config)
-                    .await
-                    .inspect(|channel| println!("Parsed {}", channel.link())),
-                channel_config,
+async fn connect(config: &Config) -> Result<Box<dyn DownloadClient>, ClientError> {
+    let client = client::from_config(config)?;
+
+    let settings = SessionSettings::new(config);
+
+    debug!(?settings, "Setting session");
+
+    rpc("configure", client.configure(&settings)).await?;
+
+    // qBittorrent has nothing to configure without settings, so it's only reached here
+    let version = rpc("version", client.version()).await?;
+
+    info!(client = %client.kind(), version, "Connected");
+
+    Ok(client)
+}
+
+/// Adds `item` unless the snapshot of the client's torrents already has it, by the infohash of its
+/// magnet link or, failing that, by the torrent the history recorded for its link.
+///
+/// Returns the torrent and its channel if it was already there. Duplicates reported
+/// by the client may only have a name and hash; their status is looked up for all of them at once afterwards.
+async fn process_item<'a>(
+    client: &dyn DownloadClient,
+    adds: &Semaphore,
+    history: &History,
+    snapshot: &[Torrent],
+    (channel_config, matched, item, link): Matched<'a>,
+    report: &Recorder,
+) -> Option<(Torrent, &'a ChannelConfig)> {
+    let download_dir = matched.directory(&channel_config.directory);
+
+    let record_add = |hash: Option<&str>, outcome: AddOutcome| {
+        report.record(|report| {
+            report.adds.push(AddReport {
+                channel: channel_config.url.clone(),
+                rule: matched.r#match.clone(),
+                title: item.title.clone(),
+                hash: hash.map(|x| x.to_owned()),
+                outcome,
+            })
+        })
+    };
+
+    let hash = match link.hash.clone() {
+        Some(hash) => Some(hash),
+        None => history
+            .get_by_link(&link.url)
+            .inspect_err(log_history_error)
+            .ok()
+            .flatten()
+            .map(|record| record.hash),
+    };
+
+    let known = hash.and_then(|hash| snapshot.iter().find(|torrent| torrent.hash == hash));
+
+    let (hash, duplicate) = match known {
+        Some(torrent) => (torrent.hash.clone(), Some(torrent.clone())),
+        None => {
+            let permit = adds.acquire().await.expect("the semaphore is never closed");
+
+            let res = rpc(
+                "add",
+                client.add(AddTorrent {
+                    link: &link.url,
+                    hash: link.hash.as_deref(),
+                    directory: &download_dir,
+                    labels: vec![BOT_LABEL.to_owned()],
+                    cookies: channel_config.cookie_header(),
+                    seeding: channel_config.seeding,
+                }),
             )
+            .await;
+
+            drop(permit);
+
+            match res {
+                Ok(Added::New(torrent)) => {
+                    count(&channel_config.url, "added");
+
+                    info!(
+                        action = "added",
+                        channel = %channel_config.url,
+                        rule = %matched.r#match,
+                        hash = %torrent.hash,
+                        name = %torrent.name,
+                        "Added"
+                    );
+
+                    (torrent.hash, None)
+                }
+                Ok(Added::Duplicate(torrent)) => (torrent.hash.clone(), Some(torrent)),
+                Err(err) => {
+                    count(&channel_config.url, "failed");
+
+                    record_add(link.hash.as_deref(), AddOutcome::Error);
+                    report.record(|report| {
+                        report.error(Some(&channel_config.url), "Can't add torrent", &err)
+                    });
+
+                    error!(
+                        channel = %channel_config.url,
+                        rule = %matched.r#match,
+                        link = %link.url,
+                        error = %err,
+                        "Can't add torrent"
+                    );
+                    return None;
+                }
+            }
+        }
+    };
+
+    history
+        .record_added(NewRecord {
+            hash: &hash,
+            guid: item.guid.as_deref(),
+            title: item.title.as_str(),
+            link: &link.url,
+            channel: &channel_config.url,
+            rule: &matched.r#match,
+            directory: &download_dir,
         })
-        .buffered(5)
-        .collect::<Vec<_>>()
-        .await
+        .inspect_err(log_history_error)
+        .ok();
+
+    record_add(
+        Some(&hash),
+        match duplicate {
+            Some(_) => AddOutcome::Duplicate,
+            None => AddOutcome::Added,
+        },
+    );
+
+    duplicate.map(|torrent| (torrent, channel_config))
+}
+
+/// Stops managed duplicates that are seeding again, unless their channel lets them seed.
+async fn handle_duplicates(
+    client: &dyn DownloadClient,
+    duplicates: Vec<(Torrent, &ChannelConfig)>,
+    report: &Recorder,
+) {
+    let (mut known, unknown) = duplicates
         .into_iter()
-        .filter_map(|(res, channel_config)| {
-            res.inspect_err(|err| println!("{err}"))
-                .ok()
-                .map(|channel| (channel, channel_config))
-        })
+        .partition::<Vec<_>, _>(|(torrent, _)| torrent.status.is_some());
+
+    if !unknown.is_empty() {
+        let hashes = unknown
+            .iter()
+            .map(|(torrent, _)| torrent.hash.clone())
+            .collect::<Vec<_>>();
+
+        match rpc("get", client.get(Some(&hashes), false)).await {
+            Ok(torrents) => known.extend(torrents.into_iter().filter_map(|torrent| {
+                let (_, channel_config) = unknown.iter().find(|(x, _)| x.hash == torrent.hash)?;
+
+                Some((torrent, *channel_config))
+            })),
+            Err(err) => {
+                error!(error = %err, "Can't look up duplicates");
+                report.record(|report| report.error(None, "Can't look up duplicates", &err));
+            }
+        }
+    }
+
+    let (seeding, others) =
+        known
+            .into_iter()
+            .partition::<Vec<_>, _>(|(torrent, channel_config)| {
+                matches!(
+                    channel_config.seeding,
+                    SeedingPolicy::Global | SeedingPolicy::Stop
+                ) && matches!(
+                    torrent.status,
+                    Some(TorrentStatus::QueuedToSeed | TorrentStatus::Seeding)
+                ) && torrent.has_label(BOT_LABEL)
+            });
+
+    for (torrent, channel_config) in others {
+        count(&channel_config.url, "duplicate");
+
+        info!(
+            action = "duplicate",
+            hash = %torrent.hash,
+            name = %torrent.name,
+            "Already added"
+        );
+    }
+
+    if seeding.is_empty() {
+        return;
+    }
+
+    let hashes = seeding
+        .iter()
+        .map(|(torrent, _)| torrent.hash.clone())
         .collect::<Vec<_>>();
 
-    println!();
-
-    let matched_items = collect_items(channels.iter_mut());
-
-    println!();
-
-    stream::iter(matched_items.into_iter())
-        .for_each_concurrent(100, |(base_directory, matched, item)| {
-            let transmission_url = transmission_url.clone();
-
-            async move {
-                let mut transmission = TransClient::new(transmission_url);
-
-                let link = item.link().unwrap_or_default();
-
-                let torrent =
-                    match add_torrent(&mut transmission, link, &matched.directory(&base_directory))
-                        .await
-                    {
-                        Ok(r) => match r {
-                            TorrentAddedOrDuplicate::TorrentDuplicate(torrent) => {
-                                let hash = torrent.hash_string.as_deref().unwrap();
-
-                                match torrent.status.unwrap() {
-                                    TorrentStatus::QueuedToSeed | TorrentStatus::Seeding
-                                        if has_label(torrent.labels.as_deref(), BOT_LABEL) =>
-                                    {
-                                        // pause_torrent
-                                        transmission
-                                            .torrent_action(
-                                                TorrentAction::Stop,
-                                                vec![Id::Hash(hash.to_owned())],
-                                            )
-                                            .await
-                                            .inspect_err(|err| eprintln!("{err}"))
-                                            .ok(); // FIXME: error handle
-
-                                        println!(
-                                            "Stopped {} | {}",
-                                            torrent.name.as_deref().unwrap(),
-                                            torrent.hash_string.as_deref().unwrap()
-                                        );
-                                    }
-                                    _ => {
-                                        println!(
-                                            "Already {} | {}",
-                                            torrent.name.as_deref().unwrap(),
-                                            torrent.hash_string.as_deref().unwrap()
-                                        );
-                                    }
-                                }
-
-                                torrent
-                            }
-                            TorrentAddedOrDuplicate::TorrentAdded(torrent) => {
-                                let hash = torrent.hash_string.as_deref().unwrap();
-                                let name = torrent.name.as_deref().unwrap();
-
-                                println!("Added {} | {}", name, hash);
-
-                                torrent
-                            }
-                            TorrentAddedOrDuplicate::Error => {
-                                return;
-                            }
-                        },
-                        Err(err) => {
-                            eprintln!("{err}");
-                            return;
-                        }
-                    };
-
-                let hash = torrent.hash_string.unwrap();
-
-                // rename
-                {
-                    let mut i = 0;
-
-                    loop {
-                        sleep(Duration::from_secs(1)).await;
-
-                        let res = rename_torrent(
-                            &mut transmission,
-                            &hash,
-                            &matched.directory(&base_directory),
-                            matched.starts_episode_at,
-                        )
-                        .await
-                        .inspect_err(|err| println!("{err}"));
-
-                        match res {
-                            Ok(Some(_name)) => break,
-                            _ => {
-                                if i > 15 {
-                                    break;
-                                }
-                                i += 1;
-                            }
-                        };
-                    }
-                };
+    let res = rpc("stop", client.stop(&hashes)).await.inspect_err(|err| {
+        error!(error = %err, "Can't stop duplicates");
+        report.record(|report| report.error(None, "Can't stop duplicates", err));
+    });
+
+    if res.is_ok() {
+        for (torrent, channel_config) in seeding {
+            count(&channel_config.url, "stopped");
+
+            report.record(|report| {
+                report.stopped.push(TorrentReport {
+                    channel: channel_config.url.clone(),
+                    hash: torrent.hash.clone(),
+                    name: torrent.name.clone(),
+                })
+            });
+
+            info!(
+                action = "stopped",
+                hash = %torrent.hash,
+                name = %torrent.name,
+                "Stopped"
+            );
+        }
+    }
+}
+
+/// How long to wait for the metadata of torrents before renaming them.
+#[derive(Debug, Clone, Copy)]
+struct RenameTimeouts {
+    /// within a run
+    wait: Duration,
+    /// across runs, since the torrent was added
+    deadline: Duration,
+}
+
+impl RenameTimeouts {
+    fn new(config: &Config) -> Self {
+        Self {
+            wait: Duration::from_secs(config.rename_wait),
+            deadline: Duration::from_secs(config.rename_deadline),
+        }
+    }
+}
 
-                // set torrent hash
-                item.set_description(hash);
+const RENAME_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Renames torrents of `channels` that are waiting for a name, including ones added by earlier runs.
+///
+/// Every pending torrent is looked up with one request per round. Torrents still without
+/// metadata are polled for up to `timeouts.wait`, then retried by later runs until `timeouts.deadline`.
+async fn rename_pending(
+    client: &dyn DownloadClient,
+    history: &History,
+    channels: &[&ChannelConfig],
+    timeouts: RenameTimeouts,
+    report: &Recorder,
+) {
+    let started_at = Instant::now();
+
+    loop {
+        let mut pending = Vec::new();
+
+        for channel_config in channels {
+            match history.pending_renames(&channel_config.url) {
+                Ok(records) => pending.extend(records.into_iter().filter_map(|record| {
+                    // rules that were removed from the configuration can't be renamed anymore
+                    let rule = channel_config.rule_of(&record)?;
+
+                    Some((*channel_config, rule, record))
+                })),
+                Err(err) => log_history_error(&err),
             }
-        })
-        .await;
+        }
 
-    match get_torrents(&mut transmission).await {
-        Ok(torrents) => {
-            // remove oldest torrents
-            let items = channels
-                .into_iter()
-                .flat_map(|(channel, _)| channel.items)
-                .collect::<Vec<_>>();
+        if pending.is_empty() {
+            return;
+        }
+
+        let hashes = pending
+            .iter()
+            .map(|(_, _, record)| record.hash.clone())
+            .collect::<Vec<_>>();
+
+        let torrents = match rpc("get", client.get(Some(&hashes), true)).await {
+            Ok(r) => r,
+            Err(err) => {
+                error!(error = %err, "Can't look up torrents to rename");
+                report
+                    .record(|report| report.error(None, "Can't look up torrents to rename", &err));
+                return;
+            }
+        };
+
+        let mut waiting = 0;
+
+        for (channel_config, rule, record) in &pending {
+            let hash = &record.hash;
 
-            let oldest_torrents = torrents
-                .into_iter()
-                .filter(|torrent| has_label(torrent.labels.as_deref(), BOT_LABEL))
-                .filter(|torrent| {
-                    !items.iter().any(|item| {
-                        item.description()
-                            .is_some_and(|desc| desc == torrent.hash_string.as_deref().unwrap())
+            let Some(torrent) = torrents.iter().find(|torrent| torrent.hash == *hash) else {
+                // removed from the client by hand
+                history
+                    .record_removed(hash)
+                    .inspect_err(log_history_error)
+                    .ok();
+                continue;
+            };
+
+            let span = info_span!(
+                "rename",
+                channel = %channel_config.url,
+                rule = %rule.r#match,
+                hash = %hash
+            );
+
+            let res = rename_torrent(client, torrent, channel_config, rule, &record.title)
+                .instrument(span.clone())
+                .await
+                .inspect_err(|err| {
+                    span.in_scope(|| error!(error = %err, "Can't rename"));
+                    report.record(|report| {
+                        report.error(Some(&channel_config.url), "Can't rename", err)
+                    });
+                });
+
+            let record_rename = |new_name: Option<&str>, outcome: RenameOutcome| {
+                report.record(|report| {
+                    report.renames.push(RenameReport {
+                        channel: channel_config.url.clone(),
+                        hash: hash.clone(),
+                        old_name: torrent.name.clone(),
+                        new_name: new_name.map(|x| x.to_owned()),
+                        outcome,
                     })
                 })
-                .collect::<Vec<_>>();
+            };
 
-            if !oldest_torrents.is_empty() {
-                transmission
-                    .torrent_remove(
-                        oldest_torrents
-                            .clone()
-                            .into_iter()
-                            .map(|torrent| Id::Hash(torrent.hash_string.unwrap()))
-                            .collect(),
-                        false,
-                    )
-                    .await
-                    .inspect_err(|err| eprintln!("{err}"))
-                    .ok();
+            let res = match res {
+                Ok(Renamed::Done(name)) => {
+                    count(&channel_config.url, "renamed");
+                    record_rename(Some(&name), RenameOutcome::Renamed);
+
+                    history.record_renamed(hash, &name)
+                }
+                Ok(Renamed::Unparseable(UnparseablePolicy::Delete, _)) => {
+                    count(&channel_config.url, "unparseable");
+                    record_rename(None, RenameOutcome::Unparseable);
+
+                    history.record_removed(hash)
+                }
+                // recorded as renamed to its current name, so the policy isn't applied again next run
+                Ok(Renamed::Unparseable(_, name)) => {
+                    count(&channel_config.url, "unparseable");
+                    record_rename(None, RenameOutcome::Unparseable);
+
+                    history.record_renamed(hash, &name)
+                }
+                Ok(Renamed::Pending) | Err(_) => {
+                    let added_for = history::now() - record.added_at;
+
+                    if added_for < timeouts.deadline.as_secs() as i64 {
+                        waiting += 1;
+                        continue;
+                    }
 
-                println!();
+                    count(&channel_config.url, "gave_up");
+                    record_rename(None, RenameOutcome::GaveUp);
 
-                for oldest_torrent in oldest_torrents {
-                    println!(
-                        "Removed {} | {}",
-                        oldest_torrent.name.unwrap(),
-                        oldest_torrent.hash_string.unwrap()
+                    span.in_scope(
+                        || warn!(action = "gave_up", title = %record.title, "Gave up renaming"),
                     );
+
+                    history.record_rename_failed(hash)
                 }
-            }
+            };
+
+            res.inspect_err(log_history_error).ok();
         }
-        Err(err) => {
-            eprintln!("{err}");
+
+        if waiting == 0 {
+            return;
+        }
+
+        if started_at.elapsed() >= timeouts.wait {
+            info!(waiting, "Waiting for metadata, retrying next run");
+            return;
         }
+
+        sleep(RENAME_POLL_INTERVAL).await;
     }
 }
 
-#[tokio::main]
-async fn main() {
-    dotenv::dotenv().ok();
+/// Settings of [`process_channels`] taken from the [`Config`].
+#[derive(Debug, Clone, Copy)]
+struct ProcessOptions {
+    rename: RenameTimeouts,
+}
+
+impl ProcessOptions {
+    fn new(config: &Config) -> Self {
+        Self {
+            rename: RenameTimeouts::new(config),
+        }
+    }
+}
+
+/// Bounds the adds in flight to `TRANSMISSION_CONCURRENCY`, shared by every poller in daemon mode.
+fn add_permits(config: &Config) -> Semaphore {
+    Semaphore::new(config.transmission_concurrency.max(1))
+}
+
+/// Adds and renames matched items of modified channels, then cleans up all `channels`.
+/// Feeds from the cache only tell which torrents are still listed.
+///
+/// The client's torrents are listed once up front, so items trss added before are recognized
+/// without asking the client to add them again. Items are added as `adds` has permits.
+async fn process_channels(
+    client: &dyn DownloadClient,
+    adds: &Semaphore,
+    history: &History,
+    channels: &[(Fetched, &ChannelConfig)],
+    options: ProcessOptions,
+    report: &Recorder,
+) {
+    let started_at = history::now();
+
+    let snapshot = rpc("get", client.get(None, false))
+        .await
+        .inspect_err(|err| {
+            error!(error = %err, "Can't list torrents");
+            report.record(|report| report.error(None, "Can't list torrents", err));
+        })
+        .ok();
+
+    if let Some(torrents) = &snapshot {
+        set_managed_torrents(torrents);
+    }
 
-    run().await;
+    let matched_items = collect_items(
+        channels
+            .iter()
+            .filter(|(channel, _)| channel.modified)
+            .map(|(channel, channel_config)| (&channel.feed, *channel_config)),
+    );
+
+    report.record(|report| {
+        for (channel_config, matched, item, link) in &matched_items {
+            report.matched(MatchedItem {
+                channel: channel_config.url.clone(),
+                rule: matched.r#match.clone(),
+                title: item.title.clone(),
+                link: link.url.clone(),
+            });
+        }
+    });
+
+    let unmodified_links = channels
+        .iter()
+        .filter(|(channel, _)| !channel.modified)
+        .flat_map(|(channel, _)| &channel.feed.items)
+        .filter_map(|item| item.torrent_link());
+
+    for link in matched_items
+        .iter()
+        .map(|(_, _, _, link)| link.clone())
+        .chain(unmodified_links)
+    {
+        history
+            .record_seen(&link.url)
+            .inspect_err(log_history_error)
+            .ok();
+    }
 
-    // TODO: graceful shutdown
+    let snapshot_torrents = snapshot.as_deref().unwrap_or_default();
+
+    let items = matched_items
+        .into_iter()
+        .map(|matched_item| {
+            process_item(
+                client,
+                adds,
+                history,
+                snapshot_torrents,
+                matched_item,
+                report,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let duplicates = future::join_all(items)
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    if !duplicates.is_empty() {
+        handle_duplicates(client, duplicates, report).await;
+    }
+
+    let channel_configs = channels
+        .iter()
+        .map(|(_, channel_config)| *channel_config)
+        .collect::<Vec<_>>();
+
+    rename_pending(client, history, &channel_configs, options.rename, report).await;
+
+    // without the snapshot, there's no telling which torrents are still managed
+    let Some(torrents) = snapshot else {
+        return;
+    };
+
+    for torrent in &torrents {
+        if torrent.percent_done == 1.0 {
+            history
+                .record_completed(&torrent.hash)
+                .inspect_err(log_history_error)
+                .ok();
+        }
+    }
+
+    // channels that failed without a cached feed don't get here, so they're never emptied
+    let expired = expired_torrents(history, &channel_configs, &torrents, |record| {
+        record.seen_at >= started_at
+    });
+
+    if expired.is_empty() {
+        return;
+    }
+
+    let hashes = expired
+        .iter()
+        .map(|(torrent, _, _)| torrent.hash.clone())
+        .collect::<Vec<_>>();
+
+    let res = rpc("remove", client.remove(&hashes, false))
+        .await
+        .inspect_err(|err| {
+            error!(error = %err, "Can't remove expired torrents");
+            report.record(|report| report.error(None, "Can't remove expired torrents", err));
+        });
+
+    if res.is_ok() {
+        for (torrent, record, reason) in expired {
+            history
+                .record_removed(&record.hash)
+                .inspect_err(log_history_error)
+                .ok();
+
+            count(&record.channel, "removed");
+
+            report.record(|report| {
+                report.removed.push(RemovedTorrent {
+                    channel: record.channel.clone(),
+                    rule: record.rule.clone(),
+                    hash: record.hash.clone(),
+                    name: torrent.name.clone(),
+                    reason: reason.to_string(),
+                })
+            });
+
+            info!(
+                action = "removed",
+                channel = %record.channel,
+                rule = %record.rule,
+                hash = %record.hash,
+                name = %torrent.name,
+                reason = %reason,
+                "Removed"
+            );
+        }
+    }
+}
+
+/// Sets the `trss_managed_torrents` metric from a listing of the client's torrents.
+fn set_managed_torrents(torrents: &[Torrent]) {
+    METRICS.torrents.reset();
+
+    for torrent in torrents {
+        if torrent.has_label(BOT_LABEL) {
+            let status = torrent
+                .status
+                .map(|x| x.to_string())
+                .unwrap_or_else(|| "unknown".to_owned());
+
+            METRICS.torrents.with_label_values(&[status.as_str()]).inc();
+        }
+    }
+}
+
+/// Managed torrents of `channels` that their retention says to remove, with the reason.
+///
+/// `in_feed` tells whether a torrent is still listed by its channel's feed.
+fn expired_torrents<'a>(
+    history: &History,
+    channels: &[&ChannelConfig],
+    torrents: &'a [Torrent],
+    in_feed: impl Fn(&Record) -> bool,
+) -> Vec<(&'a Torrent, Record, Reason)> {
+    let now = history::now();
+
+    let find = |hash: &str| {
+        torrents
+            .iter()
+            .find(|torrent| torrent.hash == hash && torrent.has_label(BOT_LABEL))
+    };
+
+    let mut expired = Vec::new();
+
+    for channel_config in channels {
+        let records = match history.managed(&channel_config.url) {
+            Ok(r) => r,
+            Err(err) => {
+                log_history_error(&err);
+                continue;
+            }
+        };
+
+        // grouped by rule, as `keep_last` counts the torrents of one rule
+        let mut rules = HashMap::<_, Vec<_>>::new();
+
+        for record in &records {
+            let Some(torrent) = find(&record.hash) else {
+                continue;
+            };
+
+            rules
+                .entry((record.rule.as_str(), record.directory.as_path()))
+                .or_default()
+                .push(Candidate {
+                    record,
+                    downloading: torrent.percent_done < 1.0,
+                    ratio: torrent.upload_ratio,
+                    in_feed: in_feed(record),
+                });
+        }
+
+        for candidates in rules.into_values() {
+            let rule = channel_config.rule_of(candidates[0].record);
+            let retention = channel_config.retention(rule);
+
+            for (candidate, reason) in retention::expired(retention, candidates, now) {
+                if let Some(torrent) = find(&candidate.record.hash) {
+                    expired.push((torrent, candidate.record.clone(), reason));
+                }
+            }
+        }
+    }
+
+    expired
+}
+
+/// Logs a fetched channel and records the fetch in the metrics and `report`. Failures aren't logged,
+/// as callers log them with what they do about it.
+fn record_fetch(
+    channel_config: &ChannelConfig,
+    res: &Result<Fetched, ChannelParseError>,
+    started_at: Instant,
+    report: &Recorder,
+) {
+    let url = channel_config.url.as_str();
+    let elapsed = started_at.elapsed();
+
+    METRICS
+        .fetch_seconds
+        .with_label_values(&[url])
+        .observe(elapsed.as_secs_f64());
+
+    let result = match res {
+        Ok(channel) if channel.modified => "fetched",
+        Ok(_) => "not_modified",
+        Err(_) => "failed",
+    };
+
+    METRICS.fetches.with_label_values(&[url, result]).inc();
+
+    report.record(|report| {
+        report.channels.push(ChannelReport {
+            url: url.to_owned(),
+            result: match res {
+                Ok(channel) if channel.modified => FetchResult::Fetched,
+                Ok(_) => FetchResult::NotModified,
+                Err(_) => FetchResult::Failed,
+            },
+            items: res.as_ref().map_or(0, |channel| channel.feed.items.len()),
+            matched: 0,
+            error: res.as_ref().err().map(|err| err.to_string()),
+        });
+
+        if let Err(err) = res {
+            report.error(Some(url), "Can't fetch feed", err);
+        }
+    });
+
+    let Ok(channel) = res else {
+        return;
+    };
+
+    let duration_ms = elapsed.as_millis() as u64;
+
+    if channel.modified {
+        info!(
+            action = "fetched",
+            channel = %channel_config.url,
+            items = channel.feed.items.len(),
+            duration_ms,
+            "Parsed {}",
+            channel.feed.link
+        );
+    } else {
+        info!(
+            action = "not_modified",
+            channel = %channel_config.url,
+            duration_ms,
+            "Not modified"
+        );
+    }
+}
+
+/// Fetches every channel. With `history`, through the cache, falling back to the cached feed of
+/// channels that fail; without, channels that fail are left out.
+async fn fetch_channels<'a>(
+    http: &HttpClient,
+    history: Option<&History>,
+    channels_config: &'a [ChannelConfig],
+    report: &Recorder,
+) -> Vec<(Fetched, &'a ChannelConfig)> {
+    let channels = stream::iter(channels_config)
+        .map(|channel_config| async move {
+            let started_at = Instant::now();
+
+            let res = match history {
+                Some(history) => fetch_channel(http, history, channel_config).await,
+                None => parse_channel(http, channel_config)
+                    .await
+                    .map(|feed| Fetched {
+                        feed,
+                        modified: true,
+                        next_fetch_at: None,
+                    }),
+            };
+
+            record_fetch(channel_config, &res, started_at, report);
+
+            (res, channel_config)
+        })
+        .buffered(5)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .filter_map(|(res, channel_config)| {
+            let channel = res
+                .inspect_err(
+                    |err| error!(channel = %channel_config.url, error = %err, "Can't fetch feed"),
+                )
+                .ok()
+                .or_else(|| cached_channel(history?, channel_config, report))?;
+
+            Some((channel, channel_config))
+        })
+        .collect::<Vec<_>>();
+
+    channels
+}
+
+/// Fetches and processes every channel once.
+///
+/// A history or download client that can't be opened ends the run early, as an error of the report.
+async fn run_channels(
+    config: &Config,
+    http: &HttpClient,
+    channels_config: &[ChannelConfig],
+    recorder: &Recorder,
+) {
+    let started_at = Instant::now();
+
+    let history = match History::open(&config.history_path) {
+        Ok(r) => r,
+        Err(err) => {
+            error!(error = %err, "Can't open the history");
+            recorder.record(|report| report.error(None, "Can't open the history", &err));
+            return;
+        }
+    };
+
+    let client = match connect(config).await {
+        Ok(r) => r,
+        Err(err) => {
+            error!(error = %err, "Can't connect to download client");
+            recorder.record(|report| report.error(None, "Can't connect to download client", &err));
+            return;
+        }
+    };
+
+    let channels = fetch_channels(http, Some(&history), channels_config, recorder).await;
+
+    process_channels(
+        client.as_ref(),
+        &add_permits(config),
+        &history,
+        &channels,
+        ProcessOptions::new(config),
+        recorder,
+    )
+    .await;
+
+    info!(
+        channels = channels.len(),
+        duration_ms = started_at.elapsed().as_millis() as u64,
+        "Finished run"
+    );
+}
+
+/// Fetches and processes every channel once, then writes the run report to `REPORT_PATH` and, with
+/// `print_report`, stdout.
+///
+/// Exits with [`EXIT_PARTIAL`] if anything in the run failed, including connecting to the
+/// download client.
+async fn run(config: Config, print_report: bool) -> ExitCode {
+    let http = http_client(&config);
+    let channels_config = load_channels(&config, &http).await;
+
+    let recorder = Recorder::default();
+    recorder.record(|report| report.started_at = history::now());
+
+    run_channels(&config, &http, &channels_config, &recorder).await;
+
+    if let Some(path) = &config.metrics_textfile {
+        if let Err(err) = METRICS.write_textfile(path) {
+            error!(path = %path.display(), error = %err, "Can't write metrics");
+        }
+    }
+
+    let mut report = recorder.finish();
+    report.finished_at = history::now();
+
+    let json = report.to_json().expect("can't serialize report");
+
+    if let Some(path) = &config.report_path {
+        if let Err(err) = std::fs::write(path, &json) {
+            error!(path = %path.display(), error = %err, "Can't write the run report");
+        }
+    }
+
+    if print_report {
+        println!("{json}");
+    }
+
+    notify(notifier(&config).as_ref(), &Event::from_report(&report)).await;
+
+    if report.failed() {
+        warn!(errors = report.errors.len(), "Run finished with errors");
+        return ExitCode::from(EXIT_PARTIAL);
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[tokio::test]
+async fn test_run_client_down() {
+    let addr = mock::unused_addr();
+
+    let dir = tempfile::tempdir().unwrap();
+    let channels = dir.path().join("channels.yaml");
+    let report = dir.path().join("report.json");
+
+    std::fs::write(&channels, "[]").unwrap();
+
+    let config = Config::from_vars(|key| match key {
+        "CHANNELS_CONFIG" => Some(channels.display().to_string()),
+        "DOWNLOAD_CLIENT" => Some("qbittorrent".to_owned()),
+        "QBITTORRENT_URL" => Some(format!("http://admin:adminadmin@{addr}")),
+        "HISTORY_PATH" => Some(dir.path().join("history.db").display().to_string()),
+        "REPORT_PATH" => Some(report.display().to_string()),
+        _ => None,
+    })
+    .unwrap();
+
+    assert_eq!(ExitCode::from(EXIT_PARTIAL), run(config, false).await);
+
+    let report =
+        serde_json::from_slice::<serde_json::Value>(&std::fs::read(report).unwrap()).unwrap();
+
+    assert_eq!(
+        "Can't connect to download client",
+        report["errors"][0]["context"]
+    );
+}
+
+/// Works out what `run` would do. The download client is only queried, never modified.
+async fn dry_run(config: Config) -> ExitCode {
+    let http = http_client(&config);
+    let channels_config = load_channels(&config, &http).await;
+
+    let history = match History::open(&config.history_path) {
+        Ok(r) => r,
+        Err(err) => {
+            error!(error = %err, "Can't open the history");
+            return ExitCode::from(EXIT_PARTIAL);
+        }
+    };
+
+    let client = download_client(&config);
+
+    // without the cache, which the next run would take as not modified
+    let channels = fetch_channels(&http, None, &channels_config, &Recorder::default()).await;
+
+    let matched_items = collect_items(
+        channels
+            .iter()
+            .map(|(channel, channel_config)| (&channel.feed, *channel_config)),
+    );
+
+    let mut plan = Plan::default();
+
+    for (channel_config, matched, item, link) in &matched_items {
+        let title = item.title.as_str();
+        let directory = matched.directory(&channel_config.directory);
+
+        let record = match &link.hash {
+            Some(hash) => history.get(hash),
+            None => history.get_by_link(&link.url),
+        }
+        .inspect_err(log_history_error)
+        .ok()
+        .flatten()
+        .filter(|record| record.removed_at.is_none());
+
+        if record.as_ref().is_none_or(|r| r.renamed_at.is_none()) {
+            plan.renames.push(PlannedRename {
+                title: title.to_owned(),
+                new_name: new_name(&directory, title, matched, title),
+                unparseable: channel_config.unparseable(matched),
+                directory: directory.clone(),
+            });
+        }
+
+        plan.adds.push(PlannedAdd {
+            channel: channel_config.url.clone(),
+            rule: matched.r#match.clone(),
+            title: title.to_owned(),
+            link: link.url.clone(),
+            directory,
+            known: record.is_some(),
+        });
+    }
+
+    let links = matched_items
+        .iter()
+        .map(|(_, _, _, link)| link.url.as_str())
+        .collect::<HashSet<_>>();
+
+    match client.get(None, false).await {
+        Ok(torrents) => {
+            let channel_configs = channels
+                .iter()
+                .map(|(_, channel_config)| *channel_config)
+                .collect::<Vec<_>>();
+
+            plan.removals = expired_torrents(&history, &channel_configs, &torrents, |record| {
+                links.contains(record.link.as_str())
+            })
+            .into_iter()
+            .map(|(torrent, record, reason)| PlannedRemoval {
+                channel: record.channel,
+                name: torrent.name.clone(),
+                hash: record.hash,
+                reason: reason.to_string(),
+            })
+            .collect();
+        }
+        Err(err) => {
+            error!(error = %err, "Can't list torrents");
+        }
+    }
+
+    println!("{}", plan.table());
+    println!("{}", plan.to_json().expect("can't serialize plan"));
+
+    ExitCode::SUCCESS
+}
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+fn backoff(interval: Duration, failures: u32) -> Duration {
+    interval
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(MAX_BACKOFF.max(interval))
+}
+
+#[test]
+fn test_backoff() {
+    let interval = Duration::from_secs(300);
+
+    assert_eq!(Duration::from_secs(600), backoff(interval, 1));
+    assert_eq!(Duration::from_secs(1200), backoff(interval, 2));
+    assert_eq!(MAX_BACKOFF, backoff(interval, 10));
+    assert_eq!(
+        Duration::from_secs(2 * 60 * 60),
+        backoff(Duration::from_secs(2 * 60 * 60), 3)
+    );
+}
+
+/// Time left until the unix timestamp `at`.
+fn until(at: Option<i64>) -> Duration {
+    let secs = at.map(|at| at - history::now()).unwrap_or_default();
+
+    Duration::from_secs(secs.max(0) as u64)
+}
+
+/// Spreads polls of channels sharing an interval by ±10%.
+fn jitter(delay: Duration) -> Duration {
+    delay.mul_f64(rand::random_range(0.9..1.1))
+}
+
+/// What the pollers of the daemon share.
+#[derive(Clone)]
+struct Shared {
+    http: HttpClient,
+    client: Arc<dyn DownloadClient>,
+    adds: Arc<Semaphore>,
+    history: Arc<History>,
+    notifier: Option<Notifier>,
+}
+
+/// Polls one channel until shutdown is requested. A poll in progress always runs to completion.
+///
+/// Each poll sends its own notification digest; a failing feed is only notified the first time.
+async fn poll_channel(
+    Shared {
+        http,
+        client,
+        adds,
+        history,
+        notifier,
+    }: Shared,
+    channel_config: ChannelConfig,
+    interval: Duration,
+    options: ProcessOptions,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut delay = Duration::ZERO;
+    let mut failures = 0;
+
+    loop {
+        tokio::select! {
+            _ = sleep(jitter(delay)) => {}
+            _ = shutdown.changed() => break,
+        }
+
+        let started_at = Instant::now();
+
+        // the daemon has no run report, only notifications
+        let report = Recorder::default();
+
+        let res = fetch_channel(&http, &history, &channel_config).await;
+
+        record_fetch(&channel_config, &res, started_at, &report);
+
+        let channel = match res {
+            Ok(channel) => {
+                failures = 0;
+                delay = interval.max(until(channel.next_fetch_at));
+
+                Some(channel)
+            }
+            Err(err) => {
+                let retry_after = match err {
+                    ChannelParseError::Status { retry_after, .. } => retry_after,
+                    _ => None,
+                };
+
+                failures += 1;
+                delay = backoff(interval, failures).max(until(retry_after));
+
+                error!(
+                    channel = %channel_config.url,
+                    error = %err,
+                    retry_in_secs = delay.as_secs(),
+                    "Can't fetch feed"
+                );
+
+                cached_channel(&history, &channel_config, &report)
+            }
+        };
+
+        if let Some(channel) = channel {
+            process_channels(
+                client.as_ref(),
+                &adds,
+                &history,
+                &[(channel, &channel_config)],
+                options,
+                &report,
+            )
+            .await;
+
+            info!(
+                channel = %channel_config.url,
+                duration_ms = started_at.elapsed().as_millis() as u64,
+                next_poll_in_secs = delay.as_secs(),
+                "Polled"
+            );
+        }
+
+        let events = Event::from_report(&report.finish())
+            .into_iter()
+            .filter(|event| failures <= 1 || !matches!(event, Event::FeedError { .. }))
+            .collect::<Vec<_>>();
+
+        notify(notifier.as_ref(), &events).await;
+    }
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("can't listen for ctrl-c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("can't listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+async fn daemon(config: Config) -> ExitCode {
+    let http = http_client(&config);
+    let channels_config = load_channels(&config, &http).await;
+
+    let history = match History::open(&config.history_path) {
+        Ok(r) => r,
+        Err(err) => {
+            error!(error = %err, "Can't open the history");
+            return ExitCode::from(EXIT_PARTIAL);
+        }
+    };
+
+    // before connecting, which may take a while, as a bad address is a configuration problem
+    let metrics = match config.metrics_addr {
+        Some(addr) => match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => Some((addr, listener)),
+            Err(err) => {
+                let problem = ConfigProblem::Malformed {
+                    key: "METRICS_ADDR".to_owned(),
+                    value: addr.to_string(),
+                    expected: "SocketAddr",
+                    reason: err.to_string(),
+                };
+
+                error!(error = %problem, "Can't serve metrics");
+                return ExitCode::from(EXIT_CONFIG);
+            }
+        },
+        None => None,
+    };
+
+    let Some(client) = connect_until_shutdown(&config).await else {
+        return ExitCode::SUCCESS;
+    };
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let shared = Shared {
+        http,
+        client: Arc::from(client),
+        adds: Arc::new(add_permits(&config)),
+        history: Arc::new(history),
+        notifier: notifier(&config),
+    };
+
+    if let Some((addr, listener)) = metrics {
+        info!(%addr, "Serving metrics");
+
+        tokio::spawn(metrics::serve(listener, &METRICS));
+    }
+
+    let mut pollers = JoinSet::new();
+
+    for channel_config in channels_config {
+        let interval = Duration::from_secs(channel_config.interval.unwrap_or(config.poll_interval));
+
+        pollers.spawn(poll_channel(
+            shared.clone(),
+            channel_config,
+            interval,
+            ProcessOptions::new(&config),
+            shutdown_rx.clone(),
+        ));
+    }
+
+    shutdown_signal().await;
+
+    info!("Shutting down, waiting for in-flight tasks");
+
+    shutdown_tx.send(true).ok();
+
+    while pollers.join_next().await.is_some() {}
+
+    ExitCode::SUCCESS
+}
+
+/// Connects to the download client, trying again every `POLL_INTERVAL` while it's unreachable.
+///
+/// Returns `None` if shutdown is requested first.
+async fn connect_until_shutdown(config: &Config) -> Option<Box<dyn DownloadClient>> {
+    let interval = Duration::from_secs(config.poll_interval);
+
+    loop {
+        match connect(config).await {
+            Ok(client) => return Some(client),
+            Err(err) => error!(
+                error = %err,
+                retry_in_secs = interval.as_secs(),
+                "Can't connect to download client"
+            ),
+        }
+
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = shutdown_signal() => return None,
+        }
+    }
+}
+
+/// Checks the channels configuration, the download client and every feed.
+async fn check(config: Config) -> ExitCode {
+    let http = &http_client(&config);
+    let channels_config = load_channels(&config, http).await;
+
+    println!(
+        "Loaded {} channels, {} rules",
+        channels_config.len(),
+        channels_config
+            .iter()
+            .map(|channel_config| channel_config.rules.len())
+            .sum::<usize>()
+    );
+
+    let mut ok = true;
+
+    let client = download_client(&config);
+
+    match client.version().await {
+        Ok(version) => println!("Connected {} {version}", client.kind()),
+        Err(err) => {
+            eprintln!("{} | {err}", client.kind());
+            ok = false;
+        }
+    }
+
+    let channels =
+        stream::iter(&channels_config)
+            .map(|channel_config| async move {
+                (parse_channel(http, channel_config).await, channel_config)
+            })
+            .buffered(5)
+            .collect::<Vec<_>>()
+            .await;
+
+    for (res, channel_config) in channels {
+        match res {
+            Ok(channel) => println!(
+                "Parsed {} | {} items",
+                channel_config.url,
+                channel.items.len()
+            ),
+            Err(err) => {
+                eprintln!("{} | {err}", channel_config.url);
+                ok = false;
+            }
+        }
+    }
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Shows where `title` would go without fetching any feed.
+async fn match_title(config: Config, title: &str) -> ExitCode {
+    let channels_config = load_channels(&config, &http_client(&config)).await;
+
+    let mut rows = Vec::new();
+
+    for channel_config in &channels_config {
+        match channel_config.find_rule(title) {
+            Ok(rule) => {
+                let directory = rule.directory(&channel_config.directory);
+                let new_name = new_name(&directory, title, rule, title);
+
+                rows.push([
+                    channel_config.url.clone(),
+                    rule.r#match.clone(),
+                    directory.display().to_string(),
+                    new_name.unwrap_or_else(|| "(unparseable)".to_owned()),
+                ]);
+            }
+            Err(Some((rule, rejection))) => {
+                println!(
+                    "Skipped {} | {} | {}",
+                    channel_config.url, rule.r#match, rejection
+                );
+            }
+            Err(None) => {}
+        }
+    }
+
+    if rows.is_empty() {
+        println!("No rule matches {title}");
+        return ExitCode::FAILURE;
+    }
+
+    print!(
+        "{}",
+        table(["CHANNEL", "RULE", "DIRECTORY", "RENAME"], &rows)
+    );
+
+    ExitCode::SUCCESS
+}
+
+async fn list(config: Config) -> ExitCode {
+    let torrents = match download_client(&config).get(None, false).await {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let rows = torrents
+        .into_iter()
+        .filter(|torrent| torrent.has_label(BOT_LABEL))
+        .map(|torrent| {
+            [
+                torrent.hash,
+                torrent
+                    .status
+                    .map(|status| status.to_string())
+                    .unwrap_or_default(),
+                torrent.name,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print!("{}", table(["HASH", "STATUS", "NAME"], &rows));
+
+    ExitCode::SUCCESS
+}
+
+fn format_time(secs: Option<i64>) -> String {
+    secs.map(|secs| {
+        humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(secs as u64)).to_string()
+    })
+    .unwrap_or_default()
+}
+
+async fn show_history(config: Config, limit: usize) -> ExitCode {
+    let records = match History::open(&config.history_path).and_then(|h| h.recent(limit)) {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let rows = records
+        .into_iter()
+        .map(|record| {
+            [
+                format_time(Some(record.added_at)),
+                format_time(record.renamed_at),
+                format_time(record.removed_at),
+                record.hash,
+                record.file_name.unwrap_or(record.title),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print!(
+        "{}",
+        table(["ADDED", "RENAMED", "REMOVED", "HASH", "NAME"], &rows)
+    );
+
+    ExitCode::SUCCESS
+}
+
+/// Subscribes to RSS feeds and adds matching torrents to Transmission or qBittorrent.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Fetch the feeds once, add and rename matching torrents and remove old ones (default)
+    Run {
+        /// Print what would be done without modifying the download client
+        #[arg(long)]
+        dry_run: bool,
+        /// Print the run report as JSON
+        #[arg(long, conflicts_with = "dry_run")]
+        report: bool,
+    },
+    /// Keep polling each channel on its own interval
+    Daemon,
+    /// Validate the channels configuration and check that the download client and the feeds are reachable
+    Check,
+    /// Show which channel, rule and directory a title would hit and what it would be renamed to
+    Match { title: String },
+    /// List torrents managed by trss
+    List,
+    /// Show torrents trss added, renamed and removed
+    History {
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+}
+
+/// Overrides for the environment variables of the same name.
+#[derive(Debug, Args)]
+struct ConfigArgs {
+    /// CHANNELS_CONFIG: URL, file path, directory of YAML fragments or `-` for stdin
+    #[arg(long, global = true)]
+    channels_config: Option<String>,
+    /// CHANNELS_CONFIG_URL
+    #[arg(long, global = true)]
+    channels_config_url: Option<String>,
+    /// DOWNLOAD_CLIENT: `transmission` or `qbittorrent`
+    #[arg(long, global = true)]
+    download_client: Option<String>,
+    /// TRANSMISSION_URL
+    #[arg(long, global = true)]
+    transmission_url: Option<String>,
+    /// TRANSMISSION_CONCURRENCY
+    #[arg(long, global = true)]
+    transmission_concurrency: Option<String>,
+    /// TRANSMISSION_USERNAME (the password is only read from TRANSMISSION_PASSWORD)
+    #[arg(long, global = true)]
+    transmission_username: Option<String>,
+    /// TRANSMISSION_CA_CERT
+    #[arg(long, global = true)]
+    transmission_ca_cert: Option<String>,
+    /// TRANSMISSION_INSECURE
+    #[arg(long, global = true, num_args = 0..=1, default_missing_value = "true")]
+    transmission_insecure: Option<String>,
+    /// QBITTORRENT_URL
+    #[arg(long, global = true)]
+    qbittorrent_url: Option<String>,
+    /// QBITTORRENT_USERNAME (the password is only read from QBITTORRENT_PASSWORD)
+    #[arg(long, global = true)]
+    qbittorrent_username: Option<String>,
+    /// DOWNLOAD_DIR
+    #[arg(long, global = true)]
+    download_dir: Option<String>,
+    /// SPEED_LIMIT_UP
+    #[arg(long, global = true)]
+    speed_limit_up: Option<String>,
+    /// SPEED_LIMIT_DOWN
+    #[arg(long, global = true)]
+    speed_limit_down: Option<String>,
+    /// DOWNLOAD_QUEUE_SIZE
+    #[arg(long, global = true)]
+    download_queue_size: Option<String>,
+    /// SEED_QUEUE_SIZE
+    #[arg(long, global = true)]
+    seed_queue_size: Option<String>,
+    /// HISTORY_PATH
+    #[arg(long, global = true)]
+    history_path: Option<String>,
+    /// POLL_INTERVAL
+    #[arg(long, global = true)]
+    poll_interval: Option<String>,
+    /// RENAME_WAIT
+    #[arg(long, global = true)]
+    rename_wait: Option<String>,
+    /// RENAME_DEADLINE
+    #[arg(long, global = true)]
+    rename_deadline: Option<String>,
+    /// FEED_USER_AGENT
+    #[arg(long, global = true)]
+    feed_user_agent: Option<String>,
+    /// FEED_CONNECT_TIMEOUT
+    #[arg(long, global = true)]
+    feed_connect_timeout: Option<String>,
+    /// FEED_READ_TIMEOUT
+    #[arg(long, global = true)]
+    feed_read_timeout: Option<String>,
+    /// FEED_RETRIES
+    #[arg(long, global = true)]
+    feed_retries: Option<String>,
+    /// FEED_PROXY
+    #[arg(long, global = true)]
+    feed_proxy: Option<String>,
+    /// LOG_LEVEL: `tracing` filter, e.g. `info` or `transmission_rss=debug,warn`
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+    /// LOG_FORMAT: `human` or `json`
+    #[arg(long, global = true)]
+    log_format: Option<String>,
+    /// METRICS_ADDR: address of `/metrics` in daemon mode, e.g. `0.0.0.0:9184`
+    #[arg(long, global = true)]
+    metrics_addr: Option<String>,
+    /// METRICS_TEXTFILE: file a run writes its metrics to
+    #[arg(long, global = true)]
+    metrics_textfile: Option<String>,
+    /// REPORT_PATH: file a run writes its JSON report to (`run --report` prints it)
+    #[arg(long, global = true)]
+    report_path: Option<String>,
+    /// NOTIFY_FORMAT (the webhook, which carries its token, is only read from NOTIFY_URL)
+    #[arg(long, global = true)]
+    notify_format: Option<String>,
+    /// NOTIFY_TEMPLATE: JSON body of `generic` webhooks
+    #[arg(long, global = true)]
+    notify_template: Option<String>,
+    /// NOTIFY_TELEGRAM_CHAT_ID
+    #[arg(long, global = true)]
+    notify_telegram_chat_id: Option<String>,
+}
+
+impl ConfigArgs {
+    /// The flag for `key` if given, otherwise the environment variable.
+    fn var(&self, key: &str) -> Option<String> {
+        let arg = match key {
+            "CHANNELS_CONFIG" => &self.channels_config,
+            "CHANNELS_CONFIG_URL" => &self.channels_config_url,
+            "DOWNLOAD_CLIENT" => &self.download_client,
+            "TRANSMISSION_URL" => &self.transmission_url,
+            "TRANSMISSION_CONCURRENCY" => &self.transmission_concurrency,
+            "TRANSMISSION_USERNAME" => &self.transmission_username,
+            "TRANSMISSION_CA_CERT" => &self.transmission_ca_cert,
+            "TRANSMISSION_INSECURE" => &self.transmission_insecure,
+            "QBITTORRENT_URL" => &self.qbittorrent_url,
+            "QBITTORRENT_USERNAME" => &self.qbittorrent_username,
+            "DOWNLOAD_DIR" => &self.download_dir,
+            "SPEED_LIMIT_UP" => &self.speed_limit_up,
+            "SPEED_LIMIT_DOWN" => &self.speed_limit_down,
+            "DOWNLOAD_QUEUE_SIZE" => &self.download_queue_size,
+            "SEED_QUEUE_SIZE" => &self.seed_queue_size,
+            "HISTORY_PATH" => &self.history_path,
+            "POLL_INTERVAL" => &self.poll_interval,
+            "RENAME_WAIT" => &self.rename_wait,
+            "RENAME_DEADLINE" => &self.rename_deadline,
+            "FEED_USER_AGENT" => &self.feed_user_agent,
+            "FEED_CONNECT_TIMEOUT" => &self.feed_connect_timeout,
+            "FEED_READ_TIMEOUT" => &self.feed_read_timeout,
+            "FEED_RETRIES" => &self.feed_retries,
+            "FEED_PROXY" => &self.feed_proxy,
+            "LOG_LEVEL" => &self.log_level,
+            "LOG_FORMAT" => &self.log_format,
+            "METRICS_ADDR" => &self.metrics_addr,
+            "METRICS_TEXTFILE" => &self.metrics_textfile,
+            "REPORT_PATH" => &self.report_path,
+            "NOTIFY_FORMAT" => &self.notify_format,
+            "NOTIFY_TEMPLATE" => &self.notify_template,
+            "NOTIFY_TELEGRAM_CHAT_ID" => &self.notify_telegram_chat_id,
+            _ => &None,
+        };
+
+        arg.clone().or_else(|| std::env::var(key).ok())
+    }
+}
+
+#[test]
+fn test_cli() {
+    use clap::CommandFactory;
+
+    Cli::command().debug_assert();
+
+    let cli = Cli::parse_from([
+        "transmission-rss",
+        "match",
+        "[SubsPlease] Slime - 62 (1080p).mkv",
+        "--transmission-url",
+        "http://localhost:9091/transmission/rpc",
+        "--transmission-insecure",
+        "--feed-connect-timeout",
+        "5",
+        "--feed-retries",
+        "0",
+        "--feed-proxy",
+        "socks5h://127.0.0.1:1080",
+        "--log-level",
+        "debug",
+        "--log-format",
+        "json",
+        "--metrics-addr",
+        "0.0.0.0:9184",
+        "--metrics-textfile",
+        "/data/trss.prom",
+        "--report-path",
+        "/data/report.json",
+        "--notify-format",
+        "telegram",
+        "--notify-template",
+        "{\"text\": \"{{text}}\"}",
+        "--notify-telegram-chat-id",
+        "42",
+    ]);
+
+    assert!(matches!(cli.command, Some(Command::Match { .. })));
+    assert_eq!(
+        Some("http://localhost:9091/transmission/rpc"),
+        cli.config.var("TRANSMISSION_URL").as_deref()
+    );
+    assert_eq!(
+        Some("true"),
+        cli.config.var("TRANSMISSION_INSECURE").as_deref()
+    );
+    assert_eq!(Some("5"), cli.config.var("FEED_CONNECT_TIMEOUT").as_deref());
+    assert_eq!(Some("0"), cli.config.var("FEED_RETRIES").as_deref());
+    assert_eq!(
+        Some("socks5h://127.0.0.1:1080"),
+        cli.config.var("FEED_PROXY").as_deref()
+    );
+    assert_eq!(Some("debug"), cli.config.var("LOG_LEVEL").as_deref());
+    assert_eq!(Some("json"), cli.config.var("LOG_FORMAT").as_deref());
+    assert_eq!(
+        Some("0.0.0.0:9184"),
+        cli.config.var("METRICS_ADDR").as_deref()
+    );
+    assert_eq!(
+        Some("/data/trss.prom"),
+        cli.config.var("METRICS_TEXTFILE").as_deref()
+    );
+    assert_eq!(
+        Some("/data/report.json"),
+        cli.config.var("REPORT_PATH").as_deref()
+    );
+    assert_eq!(Some("telegram"), cli.config.var("NOTIFY_FORMAT").as_deref());
+    assert_eq!(
+        Some("{\"text\": \"{{text}}\"}"),
+        cli.config.var("NOTIFY_TEMPLATE").as_deref()
+    );
+    assert_eq!(
+        Some("42"),
+        cli.config.var("NOTIFY_TELEGRAM_CHAT_ID").as_deref()
+    );
+
+    // secrets are only read from the environment, as flags would put them in the process list
+    for flag in [
+        "--transmission-password",
+        "--qbittorrent-password",
+        "--notify-url",
+    ] {
+        assert!(Cli::try_parse_from(["transmission-rss", flag, "s3cret"]).is_err());
+    }
+}
+
+/// Exit code for an invalid configuration (`EX_CONFIG` from sysexits.h).
+const EXIT_CONFIG: u8 = 78;
+
+/// Exit code for a run where something failed (`EX_TEMPFAIL` from sysexits.h), as the next run may
+/// well get through.
+const EXIT_PARTIAL: u8 = 75;
+
+/// Logs to stderr, so stdout only has the output of commands like `run --dry-run`.
+fn init_logging(config: &Config) -> Result<(), tracing_subscriber::filter::ParseError> {
+    let filter = EnvFilter::try_new(&config.log_level)?;
+
+    let logger = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match config.log_format {
+        LogFormat::Human => logger.init(),
+        LogFormat::Json => logger.json().flatten_event(true).init(),
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenv::dotenv().ok();
+
+    let cli = Cli::parse();
+
+    let config = match Config::from_vars(|key| cli.config.var(key)) {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::from(EXIT_CONFIG);
+        }
+    };
+
+    if let Err(err) = init_logging(&config) {
+        eprintln!("LOG_LEVEL: {err}");
+        return ExitCode::from(EXIT_CONFIG);
+    }
+
+    match cli.command.unwrap_or(Command::Run {
+        dry_run: false,
+        report: false,
+    }) {
+        Command::Run {
+            dry_run: false,
+            report,
+        } => run(config, report).await,
+        Command::Run { dry_run: true, .. } => dry_run(config).await,
+        Command::Daemon => daemon(config).await,
+        Command::Check => check(config).await,
+        Command::Match { title } => match_title(config, &title).await,
+        Command::List => list(config).await,
+        Command::History { limit } => show_history(config, limit).await,
+    }
 }
@@ -0,0 +1,169 @@
+use std::{fs, io, path::Path, sync::LazyLock};
+
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::warn;
+
+/// Seconds, from a quick feed or RPC up to a hung one.
+const LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    /// items and torrents by channel and outcome, e.g. `matched`, `added` or `removed`
+    pub items: IntCounterVec,
+    /// feed fetches by channel and result: `fetched`, `not_modified`, `cached` or `failed`
+    pub fetches: IntCounterVec,
+    pub fetch_seconds: HistogramVec,
+    /// by [`DownloadClient`](crate::client::DownloadClient) method, e.g. `add`
+    pub request_seconds: HistogramVec,
+    /// managed torrents by status, as of the last listing
+    pub torrents: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let items = IntCounterVec::new(
+            Opts::new("trss_items_total", "Feed items and torrents by outcome"),
+            &["channel", "outcome"],
+        )
+        .unwrap();
+        let fetches = IntCounterVec::new(
+            Opts::new("trss_feed_fetches_total", "Feed fetches by result"),
+            &["channel", "result"],
+        )
+        .unwrap();
+        let fetch_seconds = HistogramVec::new(
+            HistogramOpts::new("trss_feed_fetch_seconds", "Time to fetch and parse a feed")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["channel"],
+        )
+        .unwrap();
+        let request_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "trss_client_request_seconds",
+                "Time of download client requests",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["method"],
+        )
+        .unwrap();
+        let torrents = IntGaugeVec::new(
+            Opts::new(
+                "trss_managed_torrents",
+                "Torrents managed by trss by status",
+            ),
+            &["status"],
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+
+        registry.register(Box::new(items.clone())).unwrap();
+        registry.register(Box::new(fetches.clone())).unwrap();
+        registry.register(Box::new(fetch_seconds.clone())).unwrap();
+        registry
+            .register(Box::new(request_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(torrents.clone())).unwrap();
+
+        Self {
+            registry,
+            items,
+            fetches,
+            fetch_seconds,
+            request_seconds,
+            torrents,
+        }
+    }
+
+    /// The Prometheus text format.
+    pub fn encode(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .expect("metrics are valid")
+    }
+
+    /// Writes the metrics for node_exporter's textfile collector, through a temporary file so the
+    /// collector never reads a partial one.
+    pub fn write_textfile(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("prom.tmp");
+
+        fs::write(&tmp, self.encode())?;
+        fs::rename(tmp, path)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Answers `GET /metrics` on `listener` until the task is dropped.
+pub async fn serve(listener: TcpListener, metrics: &'static Metrics) {
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!(error = %err, "Can't accept metrics connection");
+                continue;
+            }
+        };
+
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).await.unwrap_or_default();
+            let req = String::from_utf8_lossy(&buf[..n]);
+
+            let mut request_line = req.split_whitespace();
+
+            let (status, body) = match (request_line.next(), request_line.next()) {
+                (Some("GET"), Some("/metrics")) => ("200 OK", metrics.encode()),
+                _ => ("404 Not Found", String::new()),
+            };
+
+            let res = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+
+            stream.write_all(res.as_bytes()).await.ok();
+        });
+    }
+}
+
+#[tokio::test]
+async fn test_serve() {
+    let metrics = Box::leak(Box::new(Metrics::new()));
+
+    metrics
+        .items
+        .with_label_values(&["https://nyaa.si/?page=rss", "added"])
+        .inc();
+    metrics.torrents.with_label_values(&["seeding"]).set(3);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(serve(listener, metrics));
+
+    let res = reqwest::get(format!("http://{addr}/metrics"))
+        .await
+        .unwrap();
+    assert_eq!(200, res.status().as_u16());
+
+    let body = res.text().await.unwrap();
+    assert!(body
+        .contains("trss_items_total{channel=\"https://nyaa.si/?page=rss\",outcome=\"added\"} 1"));
+    assert!(body.contains("trss_managed_torrents{status=\"seeding\"} 3"));
+
+    let res = reqwest::get(format!("http://{addr}/")).await.unwrap();
+    assert_eq!(404, res.status().as_u16());
+}
@@ -0,0 +1,126 @@
+//! HTTP servers for tests.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// A request to [`mock_server`].
+#[derive(Debug)]
+pub struct MockRequest {
+    pub method: String,
+    /// with the query
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockRequest {
+    /// The value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The body as an URL-encoded form.
+    pub fn form(&self) -> Vec<(String, String)> {
+        url::form_urlencoded::parse(self.body.as_bytes())
+            .into_owned()
+            .collect()
+    }
+}
+
+/// Serves HTTP with `respond`, which gives the status, extra headers and body for each request,
+/// and returns the address and the requests it got.
+pub async fn mock_server(
+    mut respond: impl FnMut(&MockRequest) -> (u16, String, String) + Send + 'static,
+) -> (SocketAddr, Arc<Mutex<Vec<MockRequest>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let log = requests.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut req = Vec::new();
+            let mut buf = [0; 4096];
+
+            let (head, body) = loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                req.extend_from_slice(&buf[..n]);
+
+                let s = String::from_utf8_lossy(&req);
+
+                let Some((head, body)) = s.split_once("\r\n\r\n") else {
+                    continue;
+                };
+
+                let len = head
+                    .lines()
+                    .find_map(|x| {
+                        x.to_lowercase()
+                            .strip_prefix("content-length:")?
+                            .trim()
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+
+                if body.len() >= len {
+                    break (head.to_owned(), body.to_owned());
+                }
+            };
+
+            let mut lines = head.lines();
+            let mut request_line = lines.next().unwrap().split(' ');
+
+            let request = MockRequest {
+                method: request_line.next().unwrap().to_owned(),
+                path: request_line.next().unwrap().to_owned(),
+                headers: lines
+                    .filter_map(|x| {
+                        let (name, value) = x.split_once(':')?;
+                        Some((name.to_owned(), value.trim().to_owned()))
+                    })
+                    .collect(),
+                body,
+            };
+
+            let (status, headers, body) = respond(&request);
+
+            log.lock().unwrap().push(request);
+
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 {status} Mock\r\nConnection: close\r\nContent-Length: {}\r\n{headers}\r\n{body}",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+        }
+    });
+
+    (addr, requests)
+}
+
+/// An address nothing listens on, for connection errors.
+pub fn unused_addr() -> SocketAddr {
+    // the port is free again once the listener is dropped
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
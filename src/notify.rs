@@ -0,0 +1,405 @@
+use std::{str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    config::Config,
+    http::without_url,
+    report::{AddOutcome, RenameOutcome, Report},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Discord rejects longer messages.
+const DISCORD_MAX_LEN: usize = 2000;
+
+/// Telegram rejects longer messages.
+const TELEGRAM_MAX_LEN: usize = 4096;
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotifyError {
+    #[error("reqwest: {0}")]
+    Reqwest(reqwest::Error),
+    #[error("HTTP {0}")]
+    Status(reqwest::StatusCode),
+}
+
+impl From<reqwest::Error> for NotifyError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Reqwest(without_url(err))
+    }
+}
+
+/// Something worth a notification.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Added {
+        channel: String,
+        rule: String,
+        title: String,
+        hash: Option<String>,
+    },
+    Renamed {
+        channel: String,
+        hash: String,
+        old_name: String,
+        new_name: String,
+    },
+    /// trname couldn't name it, or its metadata never came
+    RenameFailed {
+        channel: String,
+        hash: String,
+        name: String,
+        reason: String,
+    },
+    Stopped {
+        channel: String,
+        hash: String,
+        name: String,
+    },
+    Removed {
+        channel: String,
+        hash: String,
+        name: String,
+        reason: String,
+    },
+    FeedError {
+        channel: String,
+        error: String,
+    },
+}
+
+impl Event {
+    /// The events of a run, in the order it went through them.
+    pub fn from_report(report: &Report) -> Vec<Self> {
+        let feed_errors = report.channels.iter().filter_map(|channel| {
+            Some(Self::FeedError {
+                channel: channel.url.clone(),
+                error: channel.error.clone()?,
+            })
+        });
+
+        let adds = report
+            .adds
+            .iter()
+            .filter(|add| add.outcome == AddOutcome::Added)
+            .map(|add| Self::Added {
+                channel: add.channel.clone(),
+                rule: add.rule.clone(),
+                title: add.title.clone(),
+                hash: add.hash.clone(),
+            });
+
+        let renames = report.renames.iter().map(|rename| match rename.outcome {
+            RenameOutcome::Renamed => Self::Renamed {
+                channel: rename.channel.clone(),
+                hash: rename.hash.clone(),
+                old_name: rename.old_name.clone(),
+                new_name: rename.new_name.clone().unwrap_or_default(),
+            },
+            RenameOutcome::Unparseable | RenameOutcome::GaveUp => Self::RenameFailed {
+                channel: rename.channel.clone(),
+                hash: rename.hash.clone(),
+                name: rename.old_name.clone(),
+                reason: match rename.outcome {
+                    RenameOutcome::Unparseable => "unparseable",
+                    _ => "no metadata",
+                }
+                .to_owned(),
+            },
+        });
+
+        let stopped = report.stopped.iter().map(|torrent| Self::Stopped {
+            channel: torrent.channel.clone(),
+            hash: torrent.hash.clone(),
+            name: torrent.name.clone(),
+        });
+
+        let removed = report.removed.iter().map(|torrent| Self::Removed {
+            channel: torrent.channel.clone(),
+            hash: torrent.hash.clone(),
+            name: torrent.name.clone(),
+            reason: torrent.reason.clone(),
+        });
+
+        feed_errors
+            .chain(adds)
+            .chain(renames)
+            .chain(stopped)
+            .chain(removed)
+            .collect()
+    }
+
+    /// One line of the digest.
+    pub fn line(&self) -> String {
+        match self {
+            Self::Added { title, .. } => format!("Added: {title}"),
+            Self::Renamed {
+                old_name, new_name, ..
+            } => format!("Renamed: {old_name} → {new_name}"),
+            Self::RenameFailed { name, reason, .. } => format!("Rename failed: {name} ({reason})"),
+            Self::Stopped { name, .. } => format!("Stopped: {name}"),
+            Self::Removed { name, reason, .. } => format!("Removed: {name} ({reason})"),
+            Self::FeedError { channel, error } => format!("Feed error: {channel} ({error})"),
+        }
+    }
+}
+
+/// Payload format of the notification webhook.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyFormat {
+    /// [`NOTIFY_TEMPLATE`](NotifyTemplate), or `{"text": ..., "events": [...]}`
+    #[default]
+    Generic,
+    Discord,
+    Slack,
+    /// `sendMessage` of the Telegram bot API
+    Telegram,
+}
+
+impl FromStr for NotifyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "generic" => Ok(Self::Generic),
+            "discord" => Ok(Self::Discord),
+            "slack" => Ok(Self::Slack),
+            "telegram" => Ok(Self::Telegram),
+            _ => Err("expected `generic`, `discord`, `slack` or `telegram`".to_owned()),
+        }
+    }
+}
+
+/// JSON body of generic webhooks. In its strings, `{{text}}` is replaced by the digest and
+/// `{{count}}` by the number of events; a string that is exactly `{{events}}` becomes the array of
+/// events.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct NotifyTemplate(Value);
+
+impl Default for NotifyTemplate {
+    fn default() -> Self {
+        Self(json!({ "text": "{{text}}", "events": "{{events}}" }))
+    }
+}
+
+impl FromStr for NotifyTemplate {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s).map(Self)
+    }
+}
+
+impl NotifyTemplate {
+    pub fn render(&self, text: &str, events: &[Event]) -> Value {
+        fn render(x: &Value, text: &str, events: &[Event]) -> Value {
+            match x {
+                Value::String(s) if s == "{{events}}" => json!(events),
+                Value::String(s) => Value::String(
+                    s.replace("{{text}}", text)
+                        .replace("{{count}}", &events.len().to_string()),
+                ),
+                Value::Array(xs) => xs.iter().map(|x| render(x, text, events)).collect(),
+                Value::Object(xs) => Value::Object(
+                    xs.iter()
+                        .map(|(k, x)| (k.clone(), render(x, text, events)))
+                        .collect(),
+                ),
+                _ => x.clone(),
+            }
+        }
+
+        render(&self.0, text, events)
+    }
+}
+
+/// Cuts `text` to at most `max` characters, marking the cut.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_owned();
+    }
+
+    let mut r = text.chars().take(max - 1).collect::<String>();
+    r.push('…');
+    r
+}
+
+/// Posts one digest of a run's events to the notification webhook.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    client: reqwest::Client,
+    url: String,
+    format: NotifyFormat,
+    template: NotifyTemplate,
+    telegram_chat_id: Option<String>,
+}
+
+impl Notifier {
+    /// `None` unless `NOTIFY_URL` is set.
+    pub fn new(config: &Config) -> Result<Option<Self>, NotifyError> {
+        let Some(url) = &config.notify_url else {
+            return Ok(None);
+        };
+
+        let client = reqwest::Client::builder()
+            .user_agent(&config.feed_user_agent)
+            .timeout(TIMEOUT)
+            .build()?;
+
+        Ok(Some(Self {
+            client,
+            url: url.expose().to_owned(),
+            format: config.notify_format,
+            template: config.notify_template.clone().unwrap_or_default(),
+            telegram_chat_id: config.notify_telegram_chat_id.clone(),
+        }))
+    }
+
+    pub fn payload(&self, events: &[Event]) -> Value {
+        let text = std::iter::once(format!("transmission-rss: {} events", events.len()))
+            .chain(events.iter().map(Event::line))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        match self.format {
+            NotifyFormat::Generic => self.template.render(&text, events),
+            NotifyFormat::Discord => json!({ "content": truncate(&text, DISCORD_MAX_LEN) }),
+            NotifyFormat::Slack => json!({ "text": text }),
+            NotifyFormat::Telegram => json!({
+                "chat_id": self.telegram_chat_id,
+                "text": truncate(&text, TELEGRAM_MAX_LEN),
+                "disable_web_page_preview": true,
+            }),
+        }
+    }
+
+    /// Posts the digest of `events`, unless there are none.
+    pub async fn send(&self, events: &[Event]) -> Result<(), NotifyError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let res = self
+            .client
+            .post(&self.url)
+            .json(&self.payload(events))
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(NotifyError::Status(res.status()));
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_template() {
+    let events = vec![Event::Added {
+        channel: "https://nyaa.si/?page=rss".to_owned(),
+        rule: "Slime".to_owned(),
+        title: "[SubsPlease] Slime - 62 (1080p).mkv".to_owned(),
+        hash: None,
+    }];
+
+    let template = "{\"msg\": \"{{count}} new\", \"details\": {\"body\": \"{{text}}\", \"items\": \"{{events}}\"}}"
+        .parse::<NotifyTemplate>()
+        .unwrap();
+
+    assert_eq!(
+        json!({
+            "msg": "1 new",
+            "details": {
+                "body": "Added: [SubsPlease] Slime - 62 (1080p).mkv",
+                "items": [{
+                    "event": "added",
+                    "channel": "https://nyaa.si/?page=rss",
+                    "rule": "Slime",
+                    "title": "[SubsPlease] Slime - 62 (1080p).mkv",
+                    "hash": null,
+                }],
+            },
+        }),
+        template.render(&events[0].line(), &events)
+    );
+}
+
+#[tokio::test]
+async fn test_send() {
+    let (addr, requests) = crate::mock::mock_server(|_| (204, String::new(), String::new())).await;
+
+    let config = Config::from_vars(|key| match key {
+        "CHANNELS_CONFIG_URL" => Some("channels.yaml".to_owned()),
+        "TRANSMISSION_URL" => Some("http://localhost:9091/transmission/rpc".to_owned()),
+        "NOTIFY_URL" => Some(format!("http://{addr}/webhook")),
+        "NOTIFY_FORMAT" => Some("discord".to_owned()),
+        _ => None,
+    })
+    .unwrap();
+
+    let notifier = Notifier::new(&config).unwrap().unwrap();
+
+    notifier
+        .send(&[
+            Event::Renamed {
+                channel: "https://nyaa.si/?page=rss".to_owned(),
+                hash: "d9fe2fdfa060a246cd86d6ca566ddc20031644fd".to_owned(),
+                old_name: "[SubsPlease] Slime - 62 (1080p).mkv".to_owned(),
+                new_name: "Slime - S03E14.mkv".to_owned(),
+            },
+            Event::FeedError {
+                channel: "https://nyaa.si/?page=rss".to_owned(),
+                error: "HTTP 503 Service Unavailable".to_owned(),
+            },
+        ])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        json!({
+            "content": "\
+transmission-rss: 2 events
+Renamed: [SubsPlease] Slime - 62 (1080p).mkv → Slime - S03E14.mkv
+Feed error: https://nyaa.si/?page=rss (HTTP 503 Service Unavailable)"
+        }),
+        serde_json::from_str::<Value>(&requests.lock().unwrap()[0].body).unwrap()
+    );
+
+    // nothing to send, so no request
+    notifier.send(&[]).await.unwrap();
+
+    assert_eq!(1, requests.lock().unwrap().len());
+}
+
+#[tokio::test]
+async fn test_send_error_without_token() {
+    let addr = crate::mock::unused_addr();
+
+    let config = Config::from_vars(|key| match key {
+        "CHANNELS_CONFIG_URL" => Some("channels.yaml".to_owned()),
+        "TRANSMISSION_URL" => Some("http://localhost:9091/transmission/rpc".to_owned()),
+        "NOTIFY_URL" => Some(format!("http://{addr}/bot123:s3cret/sendMessage")),
+        "NOTIFY_FORMAT" => Some("telegram".to_owned()),
+        "NOTIFY_TELEGRAM_CHAT_ID" => Some("42".to_owned()),
+        _ => None,
+    })
+    .unwrap();
+
+    let err = Notifier::new(&config)
+        .unwrap()
+        .unwrap()
+        .send(&[Event::FeedError {
+            channel: "https://nyaa.si/?page=rss".to_owned(),
+            error: "HTTP 503 Service Unavailable".to_owned(),
+        }])
+        .await
+        .unwrap_err();
+
+    assert!(!err.to_string().contains("s3cret"));
+}
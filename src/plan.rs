@@ -0,0 +1,144 @@
+use std::{fmt::Write, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::rename::UnparseablePolicy;
+
+/// What a run would do, computed without touching Transmission.
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub adds: Vec<PlannedAdd>,
+    pub renames: Vec<PlannedRename>,
+    pub removals: Vec<PlannedRemoval>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedAdd {
+    pub channel: String,
+    pub rule: String,
+    pub title: String,
+    pub link: String,
+    pub directory: PathBuf,
+    /// already in the history, so Transmission would report a duplicate
+    pub known: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedRename {
+    pub title: String,
+    pub directory: PathBuf,
+    /// `None` if trname can't parse the title
+    pub new_name: Option<String>,
+    /// applied if `new_name` is `None`
+    pub unparseable: UnparseablePolicy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedRemoval {
+    pub channel: String,
+    pub name: String,
+    pub hash: String,
+    /// why the retention removes it
+    pub reason: String,
+}
+
+impl Plan {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn table(&self) -> String {
+        let mut rows = Vec::new();
+
+        for add in &self.adds {
+            rows.push([
+                if add.known { "already" } else { "add" }.to_owned(),
+                add.title.clone(),
+                add.directory.display().to_string(),
+            ]);
+        }
+
+        for rename in &self.renames {
+            rows.push([
+                "rename".to_owned(),
+                rename.title.clone(),
+                rename
+                    .new_name
+                    .clone()
+                    .unwrap_or_else(|| format!("(unparseable, {})", rename.unparseable)),
+            ]);
+        }
+
+        for removal in &self.removals {
+            rows.push([
+                "remove".to_owned(),
+                removal.name.clone(),
+                removal.hash.clone(),
+            ]);
+        }
+
+        table(["ACTION", "TORRENT", "TARGET"], &rows)
+    }
+}
+
+/// Left-aligned columns separated by two spaces.
+pub fn table<const N: usize>(header: [&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = header.map(|x| x.chars().count());
+
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+
+    let mut write_row = |cells: [&str; N]| {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        writeln!(out, "{}", line.trim_end()).ok();
+    };
+
+    write_row(header);
+
+    for row in rows {
+        write_row(row.each_ref().map(String::as_str));
+    }
+
+    out
+}
+
+#[test]
+fn test_plan_table() {
+    let plan = Plan {
+        adds: vec![PlannedAdd {
+            channel: "https://nyaa.si/?page=rss".to_owned(),
+            rule: "Slime".to_owned(),
+            title: "[SubsPlease] Slime - 62 (1080p).mkv".to_owned(),
+            link: "magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH5".to_owned(),
+            directory: PathBuf::from("/downloads/Slime"),
+            known: false,
+        }],
+        renames: vec![PlannedRename {
+            title: "[SubsPlease] Slime - 62 (1080p).mkv".to_owned(),
+            directory: PathBuf::from("/downloads/Slime"),
+            new_name: None,
+            unparseable: UnparseablePolicy::LabelForReview,
+        }],
+        removals: vec![],
+    };
+
+    assert_eq!(
+        "\
+ACTION  TORRENT                              TARGET
+add     [SubsPlease] Slime - 62 (1080p).mkv  /downloads/Slime
+rename  [SubsPlease] Slime - 62 (1080p).mkv  (unparseable, label-for-review)
+",
+        plan.table()
+    );
+}
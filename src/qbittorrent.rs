@@ -0,0 +1,927 @@
+use std::{path::Path, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{COOKIE, SET_COOKIE},
+    RequestBuilder, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use tokio::time::sleep;
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    client::{
+        AddTorrent, Added, ClientError, ClientKind, DownloadClient, SessionSettings, Torrent,
+        TorrentStatus,
+    },
+    config::{Config, Secret, SeedingPolicy},
+    feed::parse_hash,
+};
+
+/// How long an added torrent may take to show up, as qBittorrent fetches `.torrent` URLs in the
+/// background.
+const ADD_WAIT: Duration = Duration::from_secs(10);
+
+const ADD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// qBittorrent's Web API (v2), as of qBittorrent 4.6.
+pub struct Qbittorrent {
+    client: reqwest::Client,
+    /// base URL of the Web UI, ending with `/`
+    url: Url,
+    username: Option<String>,
+    password: Option<Secret>,
+    /// session cookie from logging in
+    cookie: Mutex<Option<String>>,
+    /// how long to wait for an added torrent, [`ADD_WAIT`] but for tests
+    add_wait: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+struct Info {
+    hash: String,
+    name: String,
+    state: String,
+    progress: f32,
+    ratio: f32,
+    /// comma-separated
+    #[serde(default)]
+    tags: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct File {
+    name: String,
+}
+
+/// Maps qBittorrent's `state` to the nearest Transmission status.
+fn status(state: &str) -> Option<TorrentStatus> {
+    let status = match state {
+        "pausedDL" | "stoppedDL" | "pausedUP" | "stoppedUP" | "error" | "missingFiles" => {
+            TorrentStatus::Stopped
+        }
+        "checkingResumeData" => TorrentStatus::QueuedToVerify,
+        "checkingDL" | "checkingUP" => TorrentStatus::Verifying,
+        "queuedDL" => TorrentStatus::QueuedToDownload,
+        "downloading" | "stalledDL" | "metaDL" | "forcedMetaDL" | "forcedDL" | "allocating"
+        | "moving" => TorrentStatus::Downloading,
+        "queuedUP" => TorrentStatus::QueuedToSeed,
+        "uploading" | "stalledUP" | "forcedUP" => TorrentStatus::Seeding,
+        _ => return None,
+    };
+
+    Some(status)
+}
+
+impl From<Info> for Torrent {
+    fn from(x: Info) -> Self {
+        Self {
+            metadata_complete: !matches!(x.state.as_str(), "metaDL" | "forcedMetaDL"),
+            status: status(&x.state),
+            hash: x.hash,
+            name: x.name,
+            labels: x
+                .tags
+                .split(',')
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_owned())
+                .collect(),
+            percent_done: x.progress,
+            upload_ratio: x.ratio,
+            files: Vec::new(),
+        }
+    }
+}
+
+/// `ratioLimit`, `seedingTimeLimit` and `inactiveSeedingTimeLimit` for `policy`, where `-1` is
+/// unlimited, or `None` to keep qBittorrent's global limits.
+fn share_limits(policy: SeedingPolicy) -> Option<(f32, i32, i32)> {
+    match policy {
+        SeedingPolicy::Global => None,
+        SeedingPolicy::Stop => Some((0.0, -1, -1)),
+        SeedingPolicy::Ratio(ratio) => Some((ratio, -1, -1)),
+        SeedingPolicy::IdleMinutes(minutes) => Some((-1.0, -1, minutes)),
+        SeedingPolicy::Forever => Some((-1.0, -1, -1)),
+    }
+}
+
+fn error_for_status(res: Response) -> Result<Response, ClientError> {
+    if !res.status().is_success() {
+        return Err(ClientError::Status(res.status()));
+    }
+
+    Ok(res)
+}
+
+impl Qbittorrent {
+    /// A client for `QBITTORRENT_URL`, logging in on the first request.
+    pub fn new(config: &Config) -> Result<Self, ClientError> {
+        let mut url = config
+            .qbittorrent_url
+            .clone()
+            .expect("QBITTORRENT_URL is required for qbittorrent");
+
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+
+        Ok(Self {
+            client: reqwest::Client::builder().build()?,
+            url,
+            username: config.qbittorrent_username.clone(),
+            password: config.qbittorrent_password.clone(),
+            cookie: Mutex::new(None),
+            add_wait: ADD_WAIT,
+        })
+    }
+
+    fn endpoint(&self, path: &str, query: &[(&str, &str)]) -> Url {
+        let mut url = self
+            .url
+            .join(&format!("api/v2/{path}"))
+            .expect("api paths are valid");
+
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+
+        url
+    }
+
+    async fn login(&self) -> Result<(), ClientError> {
+        let username = self.username.clone().unwrap_or_default();
+        let password = self
+            .password
+            .as_ref()
+            .map(|x| x.expose().to_owned())
+            .unwrap_or_default();
+
+        let res = self
+            .client
+            .post(self.endpoint("auth/login", &[]))
+            .form(&[("username", username), ("password", password)])
+            .send()
+            .await?;
+
+        let res = error_for_status(res)?;
+
+        let cookie = res
+            .headers()
+            .get(SET_COOKIE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.split(';').next())
+            .map(|x| x.to_owned());
+
+        if res.text().await?.trim() != "Ok." {
+            return Err(ClientError::Login);
+        }
+
+        *self.cookie.lock().unwrap() = cookie;
+
+        Ok(())
+    }
+
+    /// Sends a request built by `req`, logging in first and again once if the session expired.
+    ///
+    /// Without a username, qBittorrent is expected to bypass authentication for trss.
+    async fn send(
+        &self,
+        req: impl Fn(&reqwest::Client) -> RequestBuilder,
+    ) -> Result<Response, ClientError> {
+        if self.cookie.lock().unwrap().is_none() && self.username.is_some() {
+            self.login().await?;
+        }
+
+        let mut retried = false;
+
+        loop {
+            let mut builder = req(&self.client);
+
+            if let Some(cookie) = self.cookie.lock().unwrap().as_deref() {
+                builder = builder.header(COOKIE, cookie);
+            }
+
+            let res = builder.send().await?;
+
+            if res.status() == StatusCode::FORBIDDEN && self.username.is_some() && !retried {
+                self.login().await?;
+                retried = true;
+                continue;
+            }
+
+            return Ok(res);
+        }
+    }
+
+    async fn post(&self, path: &str, form: &[(&str, &str)]) -> Result<Response, ClientError> {
+        let url = self.endpoint(path, &[]);
+
+        self.send(|client| client.post(url.clone()).form(form))
+            .await
+    }
+
+    async fn get_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, ClientError> {
+        let url = self.endpoint(path, query);
+
+        let res = self.send(|client| client.get(url.clone())).await?;
+
+        Ok(error_for_status(res)?.json().await?)
+    }
+
+    async fn info(&self, query: &[(&str, &str)]) -> Result<Vec<Torrent>, ClientError> {
+        let info = self.get_json::<Vec<Info>>("torrents/info", query).await?;
+
+        Ok(info.into_iter().map(Torrent::from).collect())
+    }
+
+    /// Drops the pending tag of a just added torrent and applies its seeding policy.
+    async fn settle(
+        &self,
+        hash: &str,
+        pending_tag: &str,
+        seeding: SeedingPolicy,
+    ) -> Result<(), ClientError> {
+        let res = self
+            .post(
+                "torrents/removeTags",
+                &[("hashes", hash), ("tags", pending_tag)],
+            )
+            .await?;
+        error_for_status(res)?;
+
+        let res = self
+            .post("torrents/deleteTags", &[("tags", pending_tag)])
+            .await?;
+        error_for_status(res)?;
+
+        if let Some((ratio, seeding_time, inactive_seeding_time)) = share_limits(seeding) {
+            let res = self
+                .post(
+                    "torrents/setShareLimits",
+                    &[
+                        ("hashes", hash),
+                        ("ratioLimit", &ratio.to_string()),
+                        ("seedingTimeLimit", &seeding_time.to_string()),
+                        (
+                            "inactiveSeedingTimeLimit",
+                            &inactive_seeding_time.to_string(),
+                        ),
+                    ],
+                )
+                .await?;
+            error_for_status(res)?;
+        }
+
+        Ok(())
+    }
+
+    /// The torrent with `hash`, if it's known and qBittorrent has it.
+    async fn existing(&self, hash: Option<&str>) -> Result<Option<Torrent>, ClientError> {
+        let Some(hash) = hash else {
+            return Ok(None);
+        };
+
+        Ok(self.info(&[("hashes", hash)]).await?.pop())
+    }
+
+    async fn files(&self, hash: &str) -> Result<Vec<String>, ClientError> {
+        let files = self
+            .get_json::<Vec<File>>("torrents/files", &[("hash", hash)])
+            .await?;
+
+        Ok(files.into_iter().map(|file| file.name).collect())
+    }
+}
+
+#[async_trait]
+impl DownloadClient for Qbittorrent {
+    fn kind(&self) -> ClientKind {
+        ClientKind::Qbittorrent
+    }
+
+    async fn version(&self) -> Result<String, ClientError> {
+        let url = self.endpoint("app/version", &[]);
+
+        let res = self.send(|client| client.get(url.clone())).await?;
+
+        Ok(error_for_status(res)?.text().await?)
+    }
+
+    async fn configure(&self, settings: &SessionSettings) -> Result<(), ClientError> {
+        let mut prefs = serde_json::Map::new();
+
+        if let Some(x) = &settings.download_dir {
+            prefs.insert("save_path".to_owned(), json!(x));
+        }
+        // KB/s to B/s; unlike Transmission, 0 is unlimited
+        if let Some(x) = settings.speed_limit_up {
+            prefs.insert("up_limit".to_owned(), json!(x * 1024));
+        }
+        if let Some(x) = settings.speed_limit_down {
+            prefs.insert("dl_limit".to_owned(), json!(x * 1024));
+        }
+        if let Some(x) = settings.download_queue_size {
+            prefs.insert("max_active_downloads".to_owned(), json!(x));
+        }
+        if let Some(x) = settings.seed_queue_size {
+            prefs.insert("max_active_uploads".to_owned(), json!(x));
+        }
+        if settings.download_queue_size.is_some() || settings.seed_queue_size.is_some() {
+            prefs.insert("queueing_enabled".to_owned(), json!(true));
+        }
+
+        if prefs.is_empty() {
+            return Ok(());
+        }
+
+        let prefs = serde_json::Value::Object(prefs).to_string();
+
+        let res = self.post("app/setPreferences", &[("json", &prefs)]).await?;
+        error_for_status(res)?;
+
+        Ok(())
+    }
+
+    /// qBittorrent doesn't say which torrent it added, so it's tagged with a temporary tag to find
+    /// it afterwards.
+    async fn add(&self, torrent: AddTorrent<'_>) -> Result<Added, ClientError> {
+        let hash = torrent
+            .hash
+            .map(|x| x.to_owned())
+            .or_else(|| parse_hash(torrent.link));
+        let pending_tag = format!("trss-adding-{:016x}", rand::random::<u64>());
+
+        let tags = torrent
+            .labels
+            .iter()
+            .chain([&pending_tag])
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(",");
+        let directory = torrent.directory.to_string_lossy();
+        let cookies = torrent.cookies.unwrap_or_default();
+
+        let mut form = vec![
+            ("urls", torrent.link),
+            ("savepath", &directory),
+            ("tags", &tags),
+        ];
+
+        if !cookies.is_empty() {
+            form.push(("cookie", &cookies));
+        }
+
+        let res = self.post("torrents/add", &form).await?;
+        let status = res.status();
+        let body = res.text().await?;
+
+        if !status.is_success() || body.trim() == "Fails." {
+            // usually a duplicate of a magnet link
+            if let Some(torrent) = self.existing(hash.as_deref()).await? {
+                return Ok(Added::Duplicate(torrent));
+            }
+
+            return Err(ClientError::Refused(body));
+        }
+
+        let mut waited = Duration::ZERO;
+
+        let added = loop {
+            if let Some(added) = self.info(&[("tag", &pending_tag)]).await?.pop() {
+                break added;
+            }
+
+            if waited >= self.add_wait {
+                // qBittorrent answers `Ok.` for a `.torrent` it already has, without adding
+                if let Some(torrent) = self.existing(hash.as_deref()).await? {
+                    return Ok(Added::Duplicate(torrent));
+                }
+
+                return Err(ClientError::Refused(
+                    "didn't show up, the link may be a duplicate or invalid".to_owned(),
+                ));
+            }
+
+            sleep(ADD_POLL_INTERVAL).await;
+            waited += ADD_POLL_INTERVAL;
+        };
+
+        if let Err(err) = self
+            .settle(&added.hash, &pending_tag, torrent.seeding)
+            .await
+        {
+            warn!(hash = %added.hash, error = %err, "Can't set the tags or share limits");
+        }
+
+        Ok(Added::New(Torrent {
+            labels: torrent.labels,
+            ..added
+        }))
+    }
+
+    async fn get(
+        &self,
+        hashes: Option<&[String]>,
+        files: bool,
+    ) -> Result<Vec<Torrent>, ClientError> {
+        let mut torrents = match hashes {
+            Some(hashes) => self.info(&[("hashes", &hashes.join("|"))]).await?,
+            None => self.info(&[]).await?,
+        };
+
+        if files {
+            for torrent in &mut torrents {
+                torrent.files = self.files(&torrent.hash).await?;
+            }
+        }
+
+        Ok(torrents)
+    }
+
+    /// Renames with `renameFile` or `renameFolder`, whichever `path` is.
+    async fn rename_path(&self, hash: &str, path: &str, name: &str) -> Result<bool, ClientError> {
+        let new_path = match path.rsplit_once('/') {
+            Some((parent, _)) => format!("{parent}/{name}"),
+            None => name.to_owned(),
+        };
+
+        let endpoint = if self.files(hash).await?.iter().any(|x| x == path) {
+            "torrents/renameFile"
+        } else {
+            "torrents/renameFolder"
+        };
+
+        let res = self
+            .post(
+                endpoint,
+                &[("hash", hash), ("oldPath", path), ("newPath", &new_path)],
+            )
+            .await?;
+
+        match res.status() {
+            x if x.is_success() => Ok(true),
+            StatusCode::BAD_REQUEST | StatusCode::CONFLICT => Ok(false),
+            x => Err(ClientError::Status(x)),
+        }
+    }
+
+    async fn skip_files(&self, hash: &str, indices: &[usize]) -> Result<(), ClientError> {
+        let ids = indices
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join("|");
+
+        let res = self
+            .post(
+                "torrents/filePrio",
+                &[("hash", hash), ("id", &ids), ("priority", "0")],
+            )
+            .await?;
+        error_for_status(res)?;
+
+        Ok(())
+    }
+
+    /// `stop` since qBittorrent 5, `pause` before.
+    async fn stop(&self, hashes: &[String]) -> Result<(), ClientError> {
+        let hashes = hashes.join("|");
+
+        let res = self.post("torrents/stop", &[("hashes", &hashes)]).await?;
+
+        let res = match res.status() {
+            StatusCode::NOT_FOUND => self.post("torrents/pause", &[("hashes", &hashes)]).await?,
+            _ => res,
+        };
+        error_for_status(res)?;
+
+        Ok(())
+    }
+
+    async fn remove(&self, hashes: &[String], delete_data: bool) -> Result<(), ClientError> {
+        let res = self
+            .post(
+                "torrents/delete",
+                &[
+                    ("hashes", &hashes.join("|")),
+                    ("deleteFiles", &delete_data.to_string()),
+                ],
+            )
+            .await?;
+        error_for_status(res)?;
+
+        Ok(())
+    }
+
+    /// Labels are qBittorrent's tags.
+    async fn set_labels(&self, hash: &str, labels: &[String]) -> Result<(), ClientError> {
+        let current = self
+            .info(&[("hashes", hash)])
+            .await?
+            .pop()
+            .map(|torrent| torrent.labels)
+            .unwrap_or_default();
+
+        let removed = current
+            .iter()
+            .filter(|x| !labels.contains(x))
+            .cloned()
+            .collect::<Vec<_>>();
+        let added = labels
+            .iter()
+            .filter(|x| !current.contains(x))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !removed.is_empty() {
+            let res = self
+                .post(
+                    "torrents/removeTags",
+                    &[("hashes", hash), ("tags", &removed.join(","))],
+                )
+                .await?;
+            error_for_status(res)?;
+        }
+
+        if !added.is_empty() {
+            let res = self
+                .post(
+                    "torrents/addTags",
+                    &[("hashes", hash), ("tags", &added.join(","))],
+                )
+                .await?;
+            error_for_status(res)?;
+        }
+
+        Ok(())
+    }
+
+    async fn set_location(&self, hash: &str, directory: &Path) -> Result<(), ClientError> {
+        let res = self
+            .post(
+                "torrents/setLocation",
+                &[("hashes", hash), ("location", &directory.to_string_lossy())],
+            )
+            .await?;
+        error_for_status(res)?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_info() {
+    let info = serde_json::from_str::<Vec<Info>>(
+        r#"[
+            {
+                "hash": "d9fe2fdfa060a246cd86d6ca566ddc20031644fd",
+                "name": "[SubsPlease] Slime - 62 (1080p).mkv",
+                "state": "stalledUP",
+                "progress": 1,
+                "ratio": 0.42,
+                "tags": "managed:transmission-rss, needs-review",
+                "save_path": "/downloads/Slime"
+            },
+            {
+                "hash": "457be58a312d7a3881783b014cbf766e370c0598",
+                "name": "457be58a312d7a3881783b014cbf766e370c0598",
+                "state": "metaDL",
+                "progress": 0,
+                "ratio": 0,
+                "tags": ""
+            }
+        ]"#,
+    )
+    .unwrap();
+
+    let torrents = info.into_iter().map(Torrent::from).collect::<Vec<_>>();
+
+    assert_eq!(Some(TorrentStatus::Seeding), torrents[0].status);
+    assert_eq!(
+        vec!["managed:transmission-rss", "needs-review"],
+        torrents[0].labels
+    );
+    assert!(torrents[0].metadata_complete);
+    assert_eq!(1.0, torrents[0].percent_done);
+
+    assert_eq!(Some(TorrentStatus::Downloading), torrents[1].status);
+    assert!(torrents[1].labels.is_empty());
+    assert!(!torrents[1].metadata_complete);
+}
+
+/// Serves the Web API with `respond` and returns a client for it and the requests it got.
+#[cfg(test)]
+async fn mock_qbittorrent(
+    username: Option<&str>,
+    respond: impl FnMut(&crate::mock::MockRequest) -> (u16, String, String) + Send + 'static,
+) -> (
+    Qbittorrent,
+    std::sync::Arc<Mutex<Vec<crate::mock::MockRequest>>>,
+) {
+    let (addr, requests) = crate::mock::mock_server(respond).await;
+
+    let config = Config::from_vars(|key| match key {
+        "CHANNELS_CONFIG_URL" => Some("channels.yaml".to_owned()),
+        "DOWNLOAD_CLIENT" => Some("qbittorrent".to_owned()),
+        "QBITTORRENT_URL" => Some(format!("http://{addr}")),
+        "QBITTORRENT_USERNAME" => username.map(|x| x.to_owned()),
+        "QBITTORRENT_PASSWORD" => username.map(|_| "adminadmin".to_owned()),
+        _ => None,
+    })
+    .unwrap();
+
+    (Qbittorrent::new(&config).unwrap(), requests)
+}
+
+#[tokio::test]
+async fn test_login() {
+    let mut logins = 0;
+    let mut versions = 0;
+
+    let (qbittorrent, requests) = mock_qbittorrent(Some("admin"), move |req| {
+        match req.path.as_str() {
+            "/api/v2/auth/login" => {
+                logins += 1;
+
+                (
+                    200,
+                    format!("Set-Cookie: SID={logins}; HttpOnly; path=/\r\n"),
+                    "Ok.".to_owned(),
+                )
+            }
+            "/api/v2/app/version" => {
+                versions += 1;
+
+                // the session expires after two requests
+                let session = if versions <= 2 { "SID=1" } else { "SID=2" };
+
+                match req.header("cookie") == Some(session) {
+                    true => (200, String::new(), "v4.6.7".to_owned()),
+                    false => (403, String::new(), "Forbidden".to_owned()),
+                }
+            }
+            _ => (404, String::new(), String::new()),
+        }
+    })
+    .await;
+
+    for _ in 0..3 {
+        assert_eq!("v4.6.7", qbittorrent.version().await.unwrap());
+    }
+
+    let requests = std::mem::take(&mut *requests.lock().unwrap());
+
+    assert_eq!(
+        vec![
+            ("/api/v2/auth/login", None),
+            ("/api/v2/app/version", Some("SID=1")),
+            ("/api/v2/app/version", Some("SID=1")),
+            ("/api/v2/app/version", Some("SID=1")),
+            ("/api/v2/auth/login", None),
+            ("/api/v2/app/version", Some("SID=2")),
+        ],
+        requests
+            .iter()
+            .map(|x| (x.path.as_str(), x.header("cookie")))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![
+            ("username".to_owned(), "admin".to_owned()),
+            ("password".to_owned(), "adminadmin".to_owned())
+        ],
+        requests[0].form()
+    );
+}
+
+#[tokio::test]
+async fn test_add() {
+    let hash = "d9fe2fdfa060a246cd86d6ca566ddc20031644fd";
+    let link = "https://nyaa.si/download/1781564.torrent";
+
+    let info = move |tags: &str| {
+        format!(
+            r#"[{{"hash":"{hash}","name":"[SubsPlease] Slime - 62 (1080p).mkv","state":"metaDL","progress":0,"ratio":0,"tags":"{tags}"}}]"#
+        )
+    };
+
+    // a new torrent shows up on the second poll
+    let mut polls = 0;
+
+    let (qbittorrent, requests) = mock_qbittorrent(None, move |req| {
+        let path = req.path.split('?').next().unwrap();
+
+        match path {
+            "/api/v2/torrents/info" => {
+                polls += 1;
+
+                let body = match polls {
+                    1 => "[]".to_owned(),
+                    _ => info("managed:transmission-rss, trss-adding-0123456789abcdef"),
+                };
+
+                (200, String::new(), body)
+            }
+            _ => (200, String::new(), "Ok.".to_owned()),
+        }
+    })
+    .await;
+
+    let added = qbittorrent
+        .add(AddTorrent {
+            link,
+            hash: None,
+            directory: Path::new("/downloads/Slime"),
+            labels: vec!["managed:transmission-rss".to_owned()],
+            cookies: None,
+            seeding: SeedingPolicy::Ratio(2.0),
+        })
+        .await
+        .unwrap();
+
+    let Added::New(torrent) = added else {
+        panic!("expected a new torrent: {added:?}");
+    };
+
+    assert_eq!(hash, torrent.hash);
+    assert_eq!(vec!["managed:transmission-rss"], torrent.labels);
+
+    let requests = std::mem::take(&mut *requests.lock().unwrap());
+
+    assert_eq!(
+        vec![
+            "/api/v2/torrents/add",
+            "/api/v2/torrents/info",
+            "/api/v2/torrents/info",
+            "/api/v2/torrents/removeTags",
+            "/api/v2/torrents/deleteTags",
+            "/api/v2/torrents/setShareLimits",
+        ],
+        requests
+            .iter()
+            .map(|x| x.path.split('?').next().unwrap())
+            .collect::<Vec<_>>()
+    );
+
+    let form = |i: usize, key: &str| {
+        requests[i]
+            .form()
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    };
+
+    assert_eq!(Some(link.to_owned()), form(0, "urls"));
+    assert_eq!(Some("/downloads/Slime".to_owned()), form(0, "savepath"));
+    assert!(form(0, "tags")
+        .unwrap()
+        .starts_with("managed:transmission-rss,trss-adding-"));
+    assert_eq!(Some("2".to_owned()), form(5, "ratioLimit"));
+
+    // qBittorrent already has it, so it never shows up with the tag
+    let (mut qbittorrent, requests) = mock_qbittorrent(None, move |req| {
+        if req.path.starts_with("/api/v2/torrents/info?hashes=") {
+            (200, String::new(), info("managed:transmission-rss"))
+        } else if req.path.starts_with("/api/v2/torrents/info") {
+            (200, String::new(), "[]".to_owned())
+        } else {
+            (200, String::new(), "Ok.".to_owned())
+        }
+    })
+    .await;
+
+    qbittorrent.add_wait = Duration::ZERO;
+
+    let add = |hash| AddTorrent {
+        link,
+        hash,
+        directory: Path::new("/downloads/Slime"),
+        labels: vec!["managed:transmission-rss".to_owned()],
+        cookies: None,
+        seeding: SeedingPolicy::Global,
+    };
+
+    let added = qbittorrent.add(add(Some(hash))).await.unwrap();
+
+    let Added::Duplicate(torrent) = added else {
+        panic!("expected a duplicate: {added:?}");
+    };
+
+    assert_eq!(hash, torrent.hash);
+    assert_eq!(
+        format!("/api/v2/torrents/info?hashes={hash}"),
+        requests.lock().unwrap().last().unwrap().path
+    );
+
+    // without the hash, it can't be told from a failed add
+    assert!(matches!(
+        qbittorrent.add(add(None)).await,
+        Err(ClientError::Refused(_))
+    ));
+
+    // the torrent is added even if its share limits can't be set
+    let (qbittorrent, _) = mock_qbittorrent(None, move |req| {
+        let path = req.path.split('?').next().unwrap();
+
+        match path {
+            "/api/v2/torrents/info" => (200, String::new(), info("managed:transmission-rss")),
+            "/api/v2/torrents/setShareLimits" => (500, String::new(), String::new()),
+            _ => (200, String::new(), "Ok.".to_owned()),
+        }
+    })
+    .await;
+
+    let added = qbittorrent
+        .add(AddTorrent {
+            seeding: SeedingPolicy::Stop,
+            ..add(Some(hash))
+        })
+        .await
+        .unwrap();
+
+    assert!(matches!(added, Added::New(torrent) if torrent.hash == hash));
+}
+
+#[tokio::test]
+async fn test_rename_path() {
+    let hash = "d9fe2fdfa060a246cd86d6ca566ddc20031644fd";
+
+    let (qbittorrent, requests) = mock_qbittorrent(None, |req| {
+        let path = req.path.split('?').next().unwrap();
+
+        match path {
+            "/api/v2/torrents/files" => (
+                200,
+                String::new(),
+                r#"[{"name":"Slime/[SubsPlease] Slime - 62 (1080p).mkv"}]"#.to_owned(),
+            ),
+            "/api/v2/torrents/renameFolder"
+                if req
+                    .form()
+                    .iter()
+                    .any(|(k, v)| k == "newPath" && v == "Taken") =>
+            {
+                (409, String::new(), String::new())
+            }
+            _ => (200, String::new(), String::new()),
+        }
+    })
+    .await;
+
+    assert!(qbittorrent
+        .rename_path(
+            hash,
+            "Slime/[SubsPlease] Slime - 62 (1080p).mkv",
+            "Slime - S03E14.mkv"
+        )
+        .await
+        .unwrap());
+    assert!(qbittorrent
+        .rename_path(hash, "Slime", "Slime (2018)")
+        .await
+        .unwrap());
+    assert!(!qbittorrent
+        .rename_path(hash, "Slime", "Taken")
+        .await
+        .unwrap());
+
+    let requests = std::mem::take(&mut *requests.lock().unwrap());
+    let renames = requests
+        .iter()
+        .filter(|x| x.method == "POST")
+        .map(|x| (x.path.as_str(), x.form()))
+        .collect::<Vec<_>>();
+
+    let form = |old: &str, new: &str| {
+        vec![
+            ("hash".to_owned(), hash.to_owned()),
+            ("oldPath".to_owned(), old.to_owned()),
+            ("newPath".to_owned(), new.to_owned()),
+        ]
+    };
+
+    assert_eq!(
+        vec![
+            (
+                "/api/v2/torrents/renameFile",
+                form(
+                    "Slime/[SubsPlease] Slime - 62 (1080p).mkv",
+                    "Slime/Slime - S03E14.mkv"
+                )
+            ),
+            (
+                "/api/v2/torrents/renameFolder",
+                form("Slime", "Slime (2018)")
+            ),
+            ("/api/v2/torrents/renameFolder", form("Slime", "Taken")),
+        ],
+        renames
+    );
+}
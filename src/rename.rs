@@ -0,0 +1,282 @@
+use std::{collections::HashMap, fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+const VIDEO_EXTENSIONS: &[&str] = &["mkv", "mp4", "m4v", "avi", "mov", "wmv", "webm", "ts"];
+
+pub fn is_video(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| VIDEO_EXTENSIONS.contains(&x.to_lowercase().as_str()))
+}
+
+/// Label for torrents trname couldn't name, so they can be found in Transmission.
+pub const NEEDS_REVIEW_LABEL: &str = "needs-review";
+
+/// What to do with a torrent trname can't name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnparseablePolicy {
+    /// remove the torrent and its data
+    Delete,
+    /// leave the torrent as it is
+    KeepOriginalName,
+    /// move the data to the channel's quarantine directory and label it for review
+    MoveToQuarantineDir,
+    /// add the `needs-review` label
+    #[default]
+    LabelForReview,
+}
+
+impl fmt::Display for UnparseablePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let x = match self {
+            Self::Delete => "delete",
+            Self::KeepOriginalName => "keep-original-name",
+            Self::MoveToQuarantineDir => "move-to-quarantine-dir",
+            Self::LabelForReview => "label-for-review",
+        };
+
+        write!(f, "{x}")
+    }
+}
+
+/// `episode` and `season` from the named groups of a regex rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Captured {
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+}
+
+impl From<&HashMap<String, String>> for Captured {
+    fn from(captures: &HashMap<String, String>) -> Self {
+        let number = |name: &str| captures.get(name)?.trim().parse().ok();
+
+        Self {
+            season: number("season"),
+            episode: number("episode"),
+        }
+    }
+}
+
+/// Names `file_name` like trname does, `{show} - S{season}E{episode}`, but with the episode (and
+/// season) a rule captured instead of the ones parsed from the file name.
+///
+/// The show and, unless captured, the season come from `directory`, e.g. `Dandadan` and 2 for
+/// `/downloads/Dandadan/Season 02`. Returns `None` without a captured episode, or if
+/// `starts_episode_at` leaves it below 1.
+pub fn name_captured(
+    directory: &Path,
+    file_name: &str,
+    captured: Captured,
+    starts_episode_at: isize,
+) -> Option<String> {
+    let episode = captured.episode? as isize - (starts_episode_at - 1);
+
+    if episode < 1 {
+        return None;
+    }
+
+    let mut components = directory
+        .components()
+        .rev()
+        .filter_map(|x| x.as_os_str().to_str());
+    let last = components.next()?;
+
+    let (show, season) = match last.strip_prefix("Season ").map(|x| x.trim().parse()) {
+        Some(Ok(season)) => (components.next()?, season),
+        _ => (last, 1),
+    };
+    let season = captured.season.unwrap_or(season);
+
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|x| x.to_str())
+        .filter(|_| is_video(file_name))
+        .map(|x| format!(".{x}"))
+        .unwrap_or_default();
+
+    Some(format!("{show} - S{season:02}E{episode:02}{extension}"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileOutcome {
+    /// new file name, without its folders
+    Renamed(String),
+    /// a video trname couldn't name
+    Unparseable,
+    /// not a video, so it isn't downloaded
+    Unwanted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    /// index in the torrent's file list
+    pub index: usize,
+    /// path inside the torrent, starting with the top-level folder
+    pub path: String,
+    pub outcome: FileOutcome,
+}
+
+/// How to rename the files of a multi-file torrent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchRename {
+    /// top-level folder of the torrent
+    pub folder: String,
+    /// clean name for `folder`, if it differs
+    pub new_folder: Option<String>,
+    pub files: Vec<FileReport>,
+}
+
+impl BatchRename {
+    /// Plans a rename of every file in `paths`, naming each video with `name` (normally trname).
+    ///
+    /// The folder is named after the last two components of `directory`,
+    /// e.g. `Dandadan - Season 02` for `/downloads/Dandadan/Season 02`.
+    pub fn new<'a>(
+        directory: &Path,
+        paths: impl IntoIterator<Item = &'a str>,
+        name: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let files = paths
+            .into_iter()
+            .enumerate()
+            .map(|(index, path)| {
+                let file_name = path.rsplit('/').next().unwrap_or(path);
+
+                let outcome = if !is_video(path) {
+                    FileOutcome::Unwanted
+                } else {
+                    match name(file_name) {
+                        Some(r) => FileOutcome::Renamed(r),
+                        None => FileOutcome::Unparseable,
+                    }
+                };
+
+                FileReport {
+                    index,
+                    path: path.to_owned(),
+                    outcome,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let folder = files
+            .first()
+            .and_then(|x| x.path.split_once('/'))
+            .map(|(folder, _)| folder.to_owned())
+            .unwrap_or_default();
+
+        let mut components = directory
+            .components()
+            .rev()
+            .filter_map(|x| x.as_os_str().to_str());
+        let new_folder = match (components.next(), components.next()) {
+            (Some(season), Some(show)) => Some(format!("{show} - {season}")),
+            (Some(show), None) => Some(show.to_owned()),
+            _ => None,
+        }
+        .filter(|x| !folder.is_empty() && *x != folder);
+
+        Self {
+            folder,
+            new_folder,
+            files,
+        }
+    }
+
+    /// Indices of files that shouldn't be downloaded.
+    pub fn unwanted(&self) -> Vec<usize> {
+        self.files
+            .iter()
+            .filter(|x| x.outcome == FileOutcome::Unwanted)
+            .map(|x| x.index)
+            .collect()
+    }
+
+    /// Whether at least one video got a name.
+    pub fn renamed_any(&self) -> bool {
+        self.files
+            .iter()
+            .any(|x| matches!(x.outcome, FileOutcome::Renamed(_)))
+    }
+}
+
+#[test]
+fn test_batch_rename() {
+    let rename = BatchRename::new(
+        Path::new("/downloads/Dandadan/Season 02"),
+        [
+            "[Group] Dandadan S2 (1080p)/[Group] Dandadan - 13 (1080p).mkv",
+            "[Group] Dandadan S2 (1080p)/[Group] Dandadan - NCOP (1080p).mkv",
+            "[Group] Dandadan S2 (1080p)/Dandadan.nfo",
+            "[Group] Dandadan S2 (1080p)/Screenshots/01.png",
+        ],
+        |name| {
+            let episode = name.split(" - ").nth(1)?.split(' ').next()?;
+            let episode = episode.parse::<u32>().ok()?;
+            Some(format!("Dandadan - S02E{episode:02}.mkv"))
+        },
+    );
+
+    assert_eq!("[Group] Dandadan S2 (1080p)", rename.folder);
+    assert_eq!(Some("Dandadan - Season 02"), rename.new_folder.as_deref());
+    assert_eq!(
+        vec![
+            FileOutcome::Renamed("Dandadan - S02E13.mkv".to_owned()),
+            FileOutcome::Unparseable,
+            FileOutcome::Unwanted,
+            FileOutcome::Unwanted,
+        ],
+        rename
+            .files
+            .iter()
+            .map(|x| x.outcome.clone())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(vec![2, 3], rename.unwanted());
+    assert!(rename.renamed_any());
+}
+
+#[test]
+fn test_name_captured() {
+    let directory = Path::new("/downloads/Dandadan/Season 02");
+    let file_name = "[SubsPlease] Dandadan - 13v2 (1080p) [0214B01E].mkv";
+
+    let captured = |season, episode| Captured { season, episode };
+
+    assert_eq!(
+        Some("Dandadan - S02E13.mkv"),
+        name_captured(directory, file_name, captured(None, Some(13)), 1).as_deref()
+    );
+    assert_eq!(
+        Some("Dandadan - S03E01.mkv"),
+        name_captured(directory, file_name, captured(Some(3), Some(13)), 13).as_deref()
+    );
+    assert_eq!(
+        Some("Dandadan - S01E13"),
+        name_captured(
+            Path::new("/downloads/Dandadan"),
+            "[SubsPlease] Dandadan - 13v2 (1080p)",
+            captured(None, Some(13)),
+            1
+        )
+        .as_deref()
+    );
+    assert_eq!(
+        None,
+        name_captured(directory, file_name, captured(Some(2), None), 1)
+    );
+    assert_eq!(
+        None,
+        name_captured(directory, file_name, captured(None, Some(12)), 13)
+    );
+
+    let captures = HashMap::from([
+        ("episode".to_owned(), "07".to_owned()),
+        ("group".to_owned(), "SubsPlease".to_owned()),
+    ]);
+
+    assert_eq!(captured(None, Some(7)), Captured::from(&captures));
+}
@@ -0,0 +1,195 @@
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+/// What a run did, written as JSON at the end of it.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// unix timestamps
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub channels: Vec<ChannelReport>,
+    pub matched: Vec<MatchedItem>,
+    pub adds: Vec<AddReport>,
+    pub renames: Vec<RenameReport>,
+    pub stopped: Vec<TorrentReport>,
+    pub removed: Vec<RemovedTorrent>,
+    pub errors: Vec<RunError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchResult {
+    Fetched,
+    NotModified,
+    /// the fetch failed, so the last cached feed was used
+    Cached,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelReport {
+    pub url: String,
+    pub result: FetchResult,
+    /// items in the feed
+    pub items: usize,
+    /// items a rule matched
+    pub matched: usize,
+    /// why the fetch failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchedItem {
+    pub channel: String,
+    pub rule: String,
+    pub title: String,
+    pub link: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddOutcome {
+    Added,
+    /// already in the download client
+    Duplicate,
+    /// see `errors`
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddReport {
+    pub channel: String,
+    pub rule: String,
+    pub title: String,
+    pub hash: Option<String>,
+    pub outcome: AddOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenameOutcome {
+    Renamed,
+    /// trname couldn't name it, so the channel's `unparseable` policy was applied
+    Unparseable,
+    /// still without metadata after `RENAME_DEADLINE`
+    GaveUp,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenameReport {
+    pub channel: String,
+    pub hash: String,
+    pub old_name: String,
+    pub new_name: Option<String>,
+    pub outcome: RenameOutcome,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TorrentReport {
+    pub channel: String,
+    pub hash: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RemovedTorrent {
+    pub channel: String,
+    pub rule: String,
+    pub hash: String,
+    pub name: String,
+    /// why the retention removed it
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunError {
+    pub channel: Option<String>,
+    /// what failed, e.g. `Can't add torrent`
+    pub context: String,
+    pub error: String,
+}
+
+impl Report {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn channel_mut(&mut self, url: &str) -> Option<&mut ChannelReport> {
+        self.channels.iter_mut().find(|x| x.url == url)
+    }
+
+    pub fn matched(&mut self, item: MatchedItem) {
+        if let Some(channel) = self.channel_mut(&item.channel) {
+            channel.matched += 1;
+        }
+
+        self.matched.push(item);
+    }
+
+    pub fn error(&mut self, channel: Option<&str>, context: &str, error: impl ToString) {
+        self.errors.push(RunError {
+            channel: channel.map(|x| x.to_owned()),
+            context: context.to_owned(),
+            error: error.to_string(),
+        });
+    }
+
+    /// Whether anything failed, even if the rest of the run went through.
+    pub fn failed(&self) -> bool {
+        !self.errors.is_empty()
+    }
+}
+
+/// Collects a [`Report`] from the concurrent tasks of a run.
+#[derive(Debug, Default)]
+pub struct Recorder(Mutex<Report>);
+
+impl Recorder {
+    pub fn record(&self, f: impl FnOnce(&mut Report)) {
+        f(&mut self.0.lock().unwrap())
+    }
+
+    pub fn finish(self) -> Report {
+        self.0.into_inner().unwrap()
+    }
+}
+
+#[test]
+fn test_report() {
+    let recorder = Recorder::default();
+
+    recorder.record(|report| {
+        report.channels.push(ChannelReport {
+            url: "https://nyaa.si/?page=rss".to_owned(),
+            result: FetchResult::NotModified,
+            items: 75,
+            matched: 0,
+            error: None,
+        });
+        report.matched(MatchedItem {
+            channel: "https://nyaa.si/?page=rss".to_owned(),
+            rule: "Slime".to_owned(),
+            title: "[SubsPlease] Slime - 62 (1080p).mkv".to_owned(),
+            link: "magnet:?xt=urn:btih:3H7C7X5AMCRENTMG23FFM3O4EABRMRH5".to_owned(),
+        });
+    });
+    recorder.record(|report| {
+        report.error(
+            Some("https://nyaa.si/?page=rss"),
+            "Can't add torrent",
+            "connection refused",
+        )
+    });
+
+    let report = recorder.finish();
+
+    assert!(report.failed());
+    assert_eq!(1, report.channels[0].matched);
+
+    let json = serde_json::from_str::<serde_json::Value>(&report.to_json().unwrap()).unwrap();
+
+    assert_eq!("not_modified", json["channels"][0]["result"]);
+    assert_eq!("Slime", json["matched"][0]["rule"]);
+    assert_eq!("Can't add torrent", json["errors"][0]["context"]);
+}
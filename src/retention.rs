@@ -0,0 +1,207 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::history::Record;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// How long managed torrents are kept. A torrent is removed once `keep_last` or `keep_days` is
+/// reached, but not before its ratio reaches `until_ratio`; with only `until_ratio`, right then.
+///
+/// Without any, torrents are removed when they're no longer in the feed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct Retention {
+    /// keep only the newest N torrents of the rule
+    #[serde(default)]
+    pub keep_last: Option<usize>,
+    /// days to keep a torrent after it finished downloading
+    #[serde(default)]
+    pub keep_days: Option<u64>,
+    /// keep at least until the upload ratio is reached
+    #[serde(default)]
+    pub until_ratio: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    NotInFeed,
+    KeepLast(usize),
+    KeepDays(u64),
+    UntilRatio(f32),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInFeed => write!(f, "not in feed"),
+            Self::KeepLast(n) => write!(f, "older than the last {n}"),
+            Self::KeepDays(days) => write!(f, "completed over {days} days ago"),
+            Self::UntilRatio(ratio) => write!(f, "ratio {ratio} reached"),
+        }
+    }
+}
+
+/// A managed torrent that is still in the download client.
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    pub record: &'a Record,
+    pub downloading: bool,
+    pub ratio: f32,
+    /// whether the feed still lists the torrent
+    pub in_feed: bool,
+}
+
+/// Which of one rule's torrents to remove under `retention`, and why.
+///
+/// Torrents that are still downloading are never removed, but count towards `keep_last`.
+pub fn expired<'a>(
+    retention: Option<Retention>,
+    mut candidates: Vec<Candidate<'a>>,
+    now: i64,
+) -> Vec<(Candidate<'a>, Reason)> {
+    candidates.sort_by_key(|x| std::cmp::Reverse(x.record.added_at));
+
+    candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(_, candidate)| !candidate.downloading)
+        .filter_map(|(i, candidate)| {
+            let reason = match retention {
+                None => (!candidate.in_feed).then_some(Reason::NotInFeed),
+                Some(retention) => {
+                    if retention
+                        .until_ratio
+                        .is_some_and(|ratio| candidate.ratio < ratio)
+                    {
+                        return None;
+                    }
+
+                    let limit = retention
+                        .keep_last
+                        .filter(|n| i >= *n)
+                        .map(Reason::KeepLast)
+                        .or_else(|| {
+                            let days = retention.keep_days?;
+                            let completed_at = candidate.record.completed_at?;
+
+                            (now - completed_at > days as i64 * DAY)
+                                .then_some(Reason::KeepDays(days))
+                        });
+
+                    match retention.until_ratio {
+                        Some(ratio)
+                            if retention.keep_last.is_none() && retention.keep_days.is_none() =>
+                        {
+                            Some(Reason::UntilRatio(ratio))
+                        }
+                        _ => limit,
+                    }
+                }
+            }?;
+
+            Some((candidate, reason))
+        })
+        .collect()
+}
+
+#[test]
+fn test_expired() {
+    use std::path::PathBuf;
+
+    let record = |hash: &str, added_at: i64, completed_at: Option<i64>| Record {
+        hash: hash.to_owned(),
+        guid: None,
+        title: hash.to_owned(),
+        link: hash.to_owned(),
+        channel: "https://nyaa.si/?page=rss".to_owned(),
+        rule: "Slime".to_owned(),
+        directory: PathBuf::from("/downloads/Slime"),
+        file_name: None,
+        added_at,
+        seen_at: added_at,
+        renamed_at: None,
+        removed_at: None,
+        rename_failed_at: None,
+        completed_at,
+    };
+
+    let now = 100 * DAY;
+    let records = [
+        record("e1", DAY, Some(2 * DAY)),
+        record("e2", 2 * DAY, Some(3 * DAY)),
+        record("e3", 98 * DAY, Some(99 * DAY)),
+        record("e4", 99 * DAY, None),
+    ];
+
+    let candidates = |in_feed: bool| {
+        records
+            .iter()
+            .map(|record| Candidate {
+                record,
+                downloading: record.completed_at.is_none(),
+                ratio: if record.hash == "e3" { 2.0 } else { 0.5 },
+                in_feed,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    fn hashes<'a>(expired: Vec<(Candidate<'a>, Reason)>) -> Vec<(&'a str, Reason)> {
+        expired
+            .into_iter()
+            .map(|(candidate, reason)| (candidate.record.hash.as_str(), reason))
+            .collect()
+    }
+
+    assert!(expired(None, candidates(true), now).is_empty());
+    assert_eq!(
+        vec![
+            ("e3", Reason::NotInFeed),
+            ("e2", Reason::NotInFeed),
+            ("e1", Reason::NotInFeed)
+        ],
+        hashes(expired(None, candidates(false), now))
+    );
+
+    let retention = Retention {
+        keep_last: Some(3),
+        keep_days: Some(30),
+        until_ratio: None,
+    };
+
+    assert_eq!(
+        vec![("e2", Reason::KeepDays(30)), ("e1", Reason::KeepLast(3))],
+        hashes(expired(Some(retention), candidates(true), now))
+    );
+
+    let until_ratio = Retention {
+        until_ratio: Some(1.5),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        vec![("e3", Reason::UntilRatio(1.5))],
+        hashes(expired(Some(until_ratio), candidates(true), now))
+    );
+
+    // the ratio has to be reached before the other limits apply
+    let retention = Retention {
+        until_ratio: Some(1.5),
+        ..retention
+    };
+
+    assert!(expired(Some(retention), candidates(true), now).is_empty());
+
+    let seeded = candidates(true)
+        .into_iter()
+        .map(|candidate| Candidate {
+            ratio: 2.0,
+            ..candidate
+        })
+        .collect();
+
+    assert_eq!(
+        vec![("e2", Reason::KeepDays(30)), ("e1", Reason::KeepLast(3))],
+        hashes(expired(Some(retention), seeded, now))
+    );
+}
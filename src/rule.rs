@@ -1,36 +1,298 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
 
+use regex::{Regex, RegexBuilder};
 use serde::Deserialize;
+use tap::Pipe;
+
+use crate::{rename::UnparseablePolicy, retention::Retention};
 
 const fn default_starts_episode_at() -> isize {
     1
 }
 
+#[derive(Debug, thiserror::Error)]
+#[error("{source}")]
+pub struct RuleError {
+    pub pattern: String,
+    #[source]
+    pub source: regex::Error,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Requires {
+    /// every term must appear in the title
+    #[serde(default)]
+    pub all: Vec<String>,
+    /// at least one term must appear in the title
+    #[serde(default)]
+    pub any: Vec<String>,
+}
+
+/// Why a title that matched a rule's `match` was still rejected by it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    Excluded(String),
+    MissingRequired(String),
+    MissingAnyOf(Vec<String>),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Excluded(term) => write!(f, "excluded by `{term}`"),
+            Self::MissingRequired(term) => write!(f, "missing required `{term}`"),
+            Self::MissingAnyOf(terms) => write!(f, "missing any of `{}`", terms.join("`, `")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleMatch {
+    NoMatch,
+    Matched,
+    Rejected(Rejection),
+}
+
 #[derive(Debug, Deserialize)]
-pub struct Rule {
+pub(crate) struct RawRule {
     #[serde(default)]
-    pub regex: bool,
+    regex: bool,
     #[serde(default)]
-    pub case_insensitive: bool,
+    case_insensitive: bool,
     #[serde(rename = "match")]
-    pub r#match: String,
+    r#match: String,
     #[serde(rename = "episode", default = "default_starts_episode_at")]
+    starts_episode_at: isize,
+    directory: PathBuf,
+    #[serde(default)]
+    excludes: Vec<String>,
+    #[serde(default)]
+    requires: Requires,
+    #[serde(default)]
+    unparseable: Option<UnparseablePolicy>,
+    #[serde(default)]
+    retention: Option<Retention>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "RawRule")]
+pub struct Rule {
+    pub regex: bool,
+    pub case_insensitive: bool,
+    pub r#match: String,
     pub starts_episode_at: isize,
     pub(crate) directory: PathBuf,
+    pub excludes: Vec<String>,
+    pub requires: Requires,
+    /// overrides the channel's `unparseable`
+    pub unparseable: Option<UnparseablePolicy>,
+    /// overrides the channel's `retention`
+    pub retention: Option<Retention>,
+    /// compiled once on deserialization when `regex` is set
+    pattern: Option<Regex>,
+}
+
+impl TryFrom<RawRule> for Rule {
+    type Error = RuleError;
+
+    fn try_from(raw: RawRule) -> Result<Self, Self::Error> {
+        let pattern = if raw.regex {
+            RegexBuilder::new(&raw.r#match)
+                .case_insensitive(raw.case_insensitive)
+                .build()
+                .map(Some)
+                .map_err(|source| RuleError {
+                    pattern: raw.r#match.clone(),
+                    source,
+                })?
+        } else {
+            None
+        };
+
+        Ok(Self {
+            regex: raw.regex,
+            case_insensitive: raw.case_insensitive,
+            r#match: raw.r#match,
+            starts_episode_at: raw.starts_episode_at,
+            directory: raw.directory,
+            excludes: raw.excludes,
+            requires: raw.requires,
+            unparseable: raw.unparseable,
+            retention: raw.retention,
+            pattern,
+        })
+    }
 }
 
 impl Rule {
     pub fn test(&self, target: &str) -> bool {
-        if self.regex {
-            unimplemented!()
-        } else if self.case_insensitive {
-            target.to_lowercase().contains(&self.r#match.to_lowercase())
+        if let Some(pattern) = &self.pattern {
+            pattern.is_match(target)
+        } else {
+            self.contains(target, &self.r#match)
+        }
+    }
+
+    /// Like [`Rule::test`], but also applies the rule's `excludes` and `requires`.
+    pub fn check(&self, target: &str) -> RuleMatch {
+        if !self.test(target) {
+            return RuleMatch::NoMatch;
+        }
+
+        if let Some(term) = self
+            .excludes
+            .iter()
+            .find(|term| self.contains(target, term))
+        {
+            return RuleMatch::Rejected(Rejection::Excluded(term.clone()));
+        }
+
+        if let Some(term) = self
+            .requires
+            .all
+            .iter()
+            .find(|term| !self.contains(target, term))
+        {
+            return RuleMatch::Rejected(Rejection::MissingRequired(term.clone()));
+        }
+
+        if !self.requires.any.is_empty()
+            && !self
+                .requires
+                .any
+                .iter()
+                .any(|term| self.contains(target, term))
+        {
+            return RuleMatch::Rejected(Rejection::MissingAnyOf(self.requires.any.clone()));
+        }
+
+        RuleMatch::Matched
+    }
+
+    fn contains(&self, target: &str, term: &str) -> bool {
+        if self.case_insensitive {
+            target.to_lowercase().contains(&term.to_lowercase())
         } else {
-            target.contains(&self.r#match)
+            target.contains(term)
         }
     }
 
+    /// Named capture groups of a regex rule (e.g. `episode`, `season`).
+    ///
+    /// Returns `None` if `target` doesn't match. Substring rules never capture anything.
+    pub fn captures(&self, target: &str) -> Option<HashMap<String, String>> {
+        let Some(pattern) = &self.pattern else {
+            return self.test(target).then(HashMap::new);
+        };
+
+        let captures = pattern.captures(target)?;
+
+        pattern
+            .capture_names()
+            .flatten()
+            .filter_map(|name| Some((name.to_owned(), captures.name(name)?.as_str().to_owned())))
+            .collect::<HashMap<_, _>>()
+            .pipe(Some)
+    }
+
     pub fn directory(&self, base: impl AsRef<Path>) -> PathBuf {
         base.as_ref().join(&self.directory)
     }
 }
+
+#[cfg(test)]
+fn rule(yaml: &str) -> Rule {
+    yaml_serde::from_str(yaml).unwrap()
+}
+
+#[test]
+fn test_regex_rule() {
+    let rule = rule(
+        "
+match: '^\\[SubsPlease\\] Slime - (?P<episode>\\d+) \\(1080p\\)'
+regex: true
+directory: Slime
+",
+    );
+
+    assert!(rule.test("[SubsPlease] Slime - 62 (1080p) [0214B01E].mkv"));
+    assert!(!rule.test("[SubsPlease] slime - 62 (1080p) [0214B01E].mkv"));
+    assert!(!rule.test("[SubsPlease] Slime - 62 (720p) [0214B01E].mkv"));
+
+    let captures = rule
+        .captures("[SubsPlease] Slime - 62 (1080p) [0214B01E].mkv")
+        .unwrap();
+
+    assert_eq!(Some("62"), captures.get("episode").map(String::as_str));
+}
+
+#[test]
+fn test_regex_rule_case_insensitive() {
+    let rule = rule(
+        "
+match: 'slime - \\d+'
+regex: true
+case_insensitive: true
+directory: Slime
+",
+    );
+
+    assert!(rule.test("[SubsPlease] Slime - 62 (1080p) [0214B01E].mkv"));
+}
+
+#[test]
+fn test_invalid_regex_rule() {
+    let err = yaml_serde::from_str::<Rule>(
+        "
+match: '(unclosed'
+regex: true
+directory: Slime
+",
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("(unclosed"));
+}
+
+#[test]
+fn test_rule_excludes_and_requires() {
+    let rule = rule(
+        "
+match: Slime
+case_insensitive: true
+excludes: [hevc]
+requires:
+  all: [1080p]
+  any: [SubsPlease, Erai-raws]
+directory: Slime
+",
+    );
+
+    assert_eq!(
+        RuleMatch::Matched,
+        rule.check("[SubsPlease] Slime - 62 (1080p) [0214B01E].mkv")
+    );
+    assert_eq!(
+        RuleMatch::NoMatch,
+        rule.check("[SubsPlease] Dandadan - 12 (1080p) [0214B01E].mkv")
+    );
+    assert_eq!(
+        RuleMatch::Rejected(Rejection::Excluded("hevc".to_owned())),
+        rule.check("[SubsPlease] Slime - 62 (1080p HEVC) [0214B01E].mkv")
+    );
+    assert_eq!(
+        RuleMatch::Rejected(Rejection::MissingRequired("1080p".to_owned())),
+        rule.check("[SubsPlease] Slime - 62 (720p) [0214B01E].mkv")
+    );
+    assert_eq!(
+        RuleMatch::Rejected(Rejection::MissingAnyOf(vec![
+            "SubsPlease".to_owned(),
+            "Erai-raws".to_owned()
+        ])),
+        rule.check("[ASW] Slime - 62 (1080p) [0214B01E].mkv")
+    );
+}
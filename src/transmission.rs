@@ -0,0 +1,434 @@
+use std::{
+    ops::{Deref, DerefMut},
+    path::Path,
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use tracing::warn;
+use transmission_rpc::{
+    types::{
+        self, BasicAuth, Id, IdleMode, RatioMode, SessionSetArgs, TorrentAction, TorrentAddArgs,
+        TorrentAddedOrDuplicate, TorrentGetField, TorrentSetArgs,
+    },
+    TransClient,
+};
+use url::Url;
+
+use crate::{
+    client::{
+        AddTorrent, Added, ClientError, ClientKind, DownloadClient, SessionSettings, Torrent,
+        TorrentStatus,
+    },
+    config::{Config, SeedingPolicy},
+};
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for ClientError {
+    fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Self::Transmission(err)
+    }
+}
+
+/// Transmission's RPC, with as many connections as requests in flight.
+///
+/// `TransClient` needs `&mut` to keep its session id, so each request takes one from a pool and
+/// puts it back afterwards.
+pub struct Transmission {
+    url: Url,
+    http: reqwest::Client,
+    auth: Option<BasicAuth>,
+    pool: Mutex<Vec<TransClient>>,
+}
+
+/// A client taken from the pool of a [`Transmission`], put back when dropped.
+struct Pooled<'a> {
+    transmission: &'a Transmission,
+    client: Option<TransClient>,
+}
+
+impl Deref for Pooled<'_> {
+    type Target = TransClient;
+
+    fn deref(&self) -> &Self::Target {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for Pooled<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for Pooled<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.transmission.pool.lock().unwrap().push(client);
+        }
+    }
+}
+
+impl Transmission {
+    /// A client for `TRANSMISSION_URL`, with its credentials and TLS settings.
+    pub fn new(config: &Config) -> Result<Self, ClientError> {
+        let mut http = reqwest::Client::builder();
+
+        if let Some(bundle) = &config.transmission_ca_cert {
+            for cert in &bundle.0 {
+                http = http.add_root_certificate(cert.clone());
+            }
+        }
+
+        let http = http
+            .danger_accept_invalid_certs(config.transmission_insecure)
+            .build()?;
+
+        let url = config
+            .transmission_url
+            .clone()
+            .expect("TRANSMISSION_URL is required for transmission");
+
+        let auth = config.transmission_username.as_ref().map(|user| BasicAuth {
+            user: user.clone(),
+            password: config
+                .transmission_password
+                .as_ref()
+                .map(|x| x.expose().to_owned())
+                .unwrap_or_default(),
+        });
+
+        Ok(Self {
+            url,
+            http,
+            auth,
+            pool: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// An idle client from the pool, or a new one.
+    fn client(&self) -> Pooled<'_> {
+        let client = self.pool.lock().unwrap().pop().unwrap_or_else(|| {
+            let mut client = TransClient::new_with_client(self.url.clone(), self.http.clone());
+
+            if let Some(auth) = &self.auth {
+                client.set_auth(auth.clone());
+            }
+
+            client
+        });
+
+        Pooled {
+            transmission: self,
+            client: Some(client),
+        }
+    }
+}
+
+/// Per-torrent seeding limits for `policy`, or `None` to keep Transmission's session limits.
+fn seed_limits(policy: SeedingPolicy) -> Option<TorrentSetArgs> {
+    let args = match policy {
+        SeedingPolicy::Global => return None,
+        SeedingPolicy::Stop => TorrentSetArgs {
+            seed_ratio_mode: Some(RatioMode::Single),
+            seed_ratio_limit: Some(0.0),
+            ..Default::default()
+        },
+        SeedingPolicy::Ratio(ratio) => TorrentSetArgs {
+            seed_ratio_mode: Some(RatioMode::Single),
+            seed_ratio_limit: Some(ratio),
+            seed_idle_mode: Some(IdleMode::Unlimited),
+            ..Default::default()
+        },
+        SeedingPolicy::IdleMinutes(minutes) => TorrentSetArgs {
+            seed_ratio_mode: Some(RatioMode::Unlimited),
+            seed_idle_mode: Some(IdleMode::Single),
+            seed_idle_limit: Some(minutes),
+            ..Default::default()
+        },
+        SeedingPolicy::Forever => TorrentSetArgs {
+            seed_ratio_mode: Some(RatioMode::Unlimited),
+            seed_idle_mode: Some(IdleMode::Unlimited),
+            ..Default::default()
+        },
+    };
+
+    Some(args)
+}
+
+fn status(x: types::TorrentStatus) -> TorrentStatus {
+    match x {
+        types::TorrentStatus::Stopped => TorrentStatus::Stopped,
+        types::TorrentStatus::QueuedToVerify => TorrentStatus::QueuedToVerify,
+        types::TorrentStatus::Verifying => TorrentStatus::Verifying,
+        types::TorrentStatus::QueuedToDownload => TorrentStatus::QueuedToDownload,
+        types::TorrentStatus::Downloading => TorrentStatus::Downloading,
+        types::TorrentStatus::QueuedToSeed => TorrentStatus::QueuedToSeed,
+        types::TorrentStatus::Seeding => TorrentStatus::Seeding,
+    }
+}
+
+impl From<types::Torrent> for Torrent {
+    fn from(x: types::Torrent) -> Self {
+        Self {
+            hash: x.hash_string.unwrap_or_default(),
+            name: x.name.unwrap_or_default(),
+            status: x.status.map(status),
+            labels: x.labels.unwrap_or_default(),
+            percent_done: x.percent_done.unwrap_or_default(),
+            upload_ratio: x.upload_ratio.unwrap_or_default(),
+            metadata_complete: x.metadata_percent_complete.is_none_or(|x| x >= 1.0),
+            files: x
+                .files
+                .unwrap_or_default()
+                .into_iter()
+                .map(|file| file.name)
+                .collect(),
+        }
+    }
+}
+
+fn ids(hashes: &[String]) -> Vec<Id> {
+    hashes.iter().cloned().map(Id::Hash).collect()
+}
+
+#[async_trait]
+impl DownloadClient for Transmission {
+    fn kind(&self) -> ClientKind {
+        ClientKind::Transmission
+    }
+
+    async fn version(&self) -> Result<String, ClientError> {
+        let res = self.client().session_get().await?;
+
+        Ok(res.arguments.version)
+    }
+
+    async fn configure(&self, settings: &SessionSettings) -> Result<(), ClientError> {
+        let args = SessionSetArgs {
+            download_dir: settings.download_dir.clone(),
+            speed_limit_up_enabled: settings.speed_limit_up.is_some().then_some(true),
+            speed_limit_up: settings.speed_limit_up,
+            speed_limit_down_enabled: settings.speed_limit_down.is_some().then_some(true),
+            speed_limit_down: settings.speed_limit_down,
+            download_queue_enabled: settings.download_queue_size.is_some().then_some(true),
+            download_queue_size: settings.download_queue_size,
+            seed_queue_enabled: settings.seed_queue_size.is_some().then_some(true),
+            seed_queue_size: settings.seed_queue_size,
+            ..Default::default()
+        };
+
+        self.client().session_set(args).await?;
+
+        Ok(())
+    }
+
+    async fn add(&self, torrent: AddTorrent<'_>) -> Result<Added, ClientError> {
+        let mut client = self.client();
+
+        let res = client
+            .torrent_add(TorrentAddArgs {
+                cookies: torrent.cookies,
+                filename: Some(torrent.link.to_owned()),
+                labels: Some(torrent.labels),
+                download_dir: torrent.directory.to_str().map(|x| x.to_owned()),
+                ..Default::default()
+            })
+            .await?;
+
+        match res.arguments {
+            TorrentAddedOrDuplicate::TorrentAdded(added) => {
+                if let Some(args) = seed_limits(torrent.seeding) {
+                    match added.hash_string.clone() {
+                        Some(hash) => {
+                            if let Err(err) = client
+                                .torrent_set(args, Some(vec![Id::Hash(hash.clone())]))
+                                .await
+                            {
+                                warn!(hash, error = %err, "Can't set the seeding limits");
+                            }
+                        }
+                        None => warn!(
+                            link = torrent.link,
+                            "Can't set the seeding limits of a torrent added without a hash"
+                        ),
+                    }
+                }
+
+                Ok(Added::New(added.into()))
+            }
+            TorrentAddedOrDuplicate::TorrentDuplicate(torrent) => {
+                Ok(Added::Duplicate(torrent.into()))
+            }
+            TorrentAddedOrDuplicate::Error => Err(ClientError::Refused(res.result)),
+        }
+    }
+
+    async fn get(
+        &self,
+        hashes: Option<&[String]>,
+        files: bool,
+    ) -> Result<Vec<Torrent>, ClientError> {
+        let mut fields = vec![
+            TorrentGetField::Id,
+            TorrentGetField::Name,
+            TorrentGetField::HashString,
+            TorrentGetField::Status,
+            TorrentGetField::Labels,
+            TorrentGetField::PercentDone,
+            TorrentGetField::UploadRatio,
+        ];
+
+        if files {
+            fields.extend([
+                TorrentGetField::MetadataPercentComplete,
+                TorrentGetField::Files,
+            ]);
+        }
+
+        let res = self
+            .client()
+            .torrent_get(Some(fields), hashes.map(ids))
+            .await?;
+
+        Ok(res
+            .arguments
+            .torrents
+            .into_iter()
+            .map(Torrent::from)
+            .collect())
+    }
+
+    async fn rename_path(&self, hash: &str, path: &str, name: &str) -> Result<bool, ClientError> {
+        let res = self
+            .client()
+            .torrent_rename_path(
+                vec![Id::Hash(hash.to_owned())],
+                path.to_owned(),
+                name.to_owned(),
+            )
+            .await?;
+
+        Ok(res.is_ok())
+    }
+
+    async fn skip_files(&self, hash: &str, indices: &[usize]) -> Result<(), ClientError> {
+        self.client()
+            .torrent_set(
+                TorrentSetArgs {
+                    files_unwanted: Some(indices.iter().map(|x| *x as i32).collect()),
+                    ..Default::default()
+                },
+                Some(vec![Id::Hash(hash.to_owned())]),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn stop(&self, hashes: &[String]) -> Result<(), ClientError> {
+        self.client()
+            .torrent_action(TorrentAction::Stop, ids(hashes))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove(&self, hashes: &[String], delete_data: bool) -> Result<(), ClientError> {
+        self.client()
+            .torrent_remove(ids(hashes), delete_data)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_labels(&self, hash: &str, labels: &[String]) -> Result<(), ClientError> {
+        self.client()
+            .torrent_set(
+                TorrentSetArgs {
+                    labels: Some(labels.to_vec()),
+                    ..Default::default()
+                },
+                Some(vec![Id::Hash(hash.to_owned())]),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_location(&self, hash: &str, directory: &Path) -> Result<(), ClientError> {
+        self.client()
+            .torrent_set_location(
+                vec![Id::Hash(hash.to_owned())],
+                directory.to_string_lossy().into_owned(),
+                Some(true),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_get_torrent() {
+    let mut transmission = TransClient::new(
+        "http://192.168.1.21:32091/transmission/rpc"
+            .parse()
+            .expect("can't parse transmission url"),
+    );
+
+    let res = transmission
+        .torrent_get(
+            Some(vec![
+                TorrentGetField::Id,
+                TorrentGetField::Name,
+                TorrentGetField::HashString,
+                TorrentGetField::Status,
+                TorrentGetField::Labels,
+                TorrentGetField::Error,
+                TorrentGetField::ErrorString,
+                TorrentGetField::DoneDate,
+                TorrentGetField::FileStats,
+                TorrentGetField::FileCount,
+                TorrentGetField::Files,
+            ]),
+            // Some(vec![Id::Hash(
+            //     "457be58a312d7a3881783b014cbf766e370c0598".to_owned(),
+            // )]),
+            None,
+        )
+        .await
+        .unwrap();
+
+    println!("{res:#?}");
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_add_torrent() {
+    let link = "magnet:?xt=urn:btih:SEFD6A5N67C2CJ3NDJI74AP6CZXTCFSZ&dn=%5BSubsPlease%5D%20Katsute%20Mahou%20Shoujo%20to%20Aku%20wa%20Tekitai%20shiteita%20-%2002%20%281080p%29%20%5BC2A5EFC3%5D.mkv&xl=767183596&tr=http%3A%2F%2Fnyaa.tracker.wf%3A7777%2Fannounce&tr=udp%3A%2F%2Ftracker.coppersurfer.tk%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker.opentrackr.org%3A1337%2Fannounce&tr=udp%3A%2F%2F9.rarbg.to%3A2710%2Fannounce&tr=udp%3A%2F%2F9.rarbg.me%3A2710%2Fannounce&tr=udp%3A%2F%2Ftracker.leechers-paradise.org%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker.internetwarriors.net%3A1337%2Fannounce&tr=udp%3A%2F%2Ftracker.cyberia.is%3A6969%2Fannounce&tr=udp%3A%2F%2Fexodus.desync.com%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker3.itzmx.com%3A6961%2Fannounce&tr=udp%3A%2F%2Ftracker.torrent.eu.org%3A451%2Fannounce&tr=udp%3A%2F%2Ftracker.tiny-vps.com%3A6969%2Fannounce&tr=udp%3A%2F%2Fretracker.lanta-net.ru%3A2710%2Fannounce&tr=http%3A%2F%2Fopen.acgnxtracker.com%3A80%2Fannounce&tr=wss%3A%2F%2Ftracker.openwebtorrent.com";
+
+    let config = Config::from_vars(|key| match key {
+        "CHANNELS_CONFIG_URL" => Some("channels.yaml".to_owned()),
+        "TRANSMISSION_URL" => Some("http://192.168.1.21:32091/transmission/rpc".to_owned()),
+        _ => None,
+    })
+    .unwrap();
+
+    let transmission = Transmission::new(&config).unwrap();
+
+    let res = transmission
+        .add(AddTorrent {
+            link,
+            hash: None,
+            directory: Path::new(
+                "/downloads/Shows (current)/Katsute Mahou Shoujo to Aku wa Tekitai shiteita/Season 01",
+            ),
+            labels: vec!["managed:transmission-rss".to_owned()],
+            cookies: None,
+            seeding: SeedingPolicy::Global,
+        })
+        .await
+        .unwrap();
+
+    println!("{res:#?}");
+}
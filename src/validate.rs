@@ -0,0 +1,421 @@
+use std::{
+    fmt,
+    path::{Component, Path},
+};
+
+use reqwest::header::{HeaderName, HeaderValue};
+
+use yaml_serde::Value;
+
+use crate::{config::ChannelConfig, rule::Rule};
+
+/// 1-based position in the channels YAML.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// file name or URL the YAML was read from
+    pub source: String,
+    pub location: Option<Location>,
+    /// `url` of the offending channel, if the problem isn't with the whole file
+    pub channel: Option<String>,
+    /// `match` of the offending rule, if the problem is with a rule
+    pub rule: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)?;
+
+        if let Some(Location { line, column }) = self.location {
+            write!(f, ":{line}:{column}")?;
+        }
+
+        if let Some(channel) = &self.channel {
+            write!(f, ": channel {channel}")?;
+        }
+
+        if let Some(rule) = &self.rule {
+            write!(f, ", rule `{rule}`")?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Every problem found in a channels configuration.
+#[derive(Debug, thiserror::Error)]
+pub struct ValidationError(pub Vec<Diagnostic>);
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} problems", self.0.len())?;
+
+        for diagnostic in &self.0 {
+            write!(f, "\n  {diagnostic}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<yaml_serde::Location> for Location {
+    fn from(x: yaml_serde::Location) -> Self {
+        Self {
+            line: x.line(),
+            column: x.column(),
+        }
+    }
+}
+
+fn location(text: &str, offset: usize) -> Location {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|x| x + 1).unwrap_or(0) + 1;
+
+    Location { line, column }
+}
+
+/// Finds `needle` in `text` at or after `from`, returning its byte offset.
+fn find(text: &str, needle: &str, from: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
+
+    text.get(from..)?.find(needle).map(|x| x + from)
+}
+
+/// Finds the mapping key `key` (followed by `:`) in `text` at or after `from`.
+fn find_key(text: &str, key: &str, mut from: usize) -> Option<usize> {
+    loop {
+        let offset = find(text, key, from)?;
+
+        if text[offset + key.len()..]
+            .trim_start_matches(' ')
+            .starts_with(':')
+        {
+            return Some(offset);
+        }
+
+        from = offset + key.len();
+    }
+}
+
+/// Whether joining `directory` onto a base directory could leave that base.
+fn escapes(directory: &Path) -> bool {
+    let mut depth = 0usize;
+
+    for component in directory.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return true,
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(x) => depth = x,
+                None => return true,
+            },
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+        }
+    }
+
+    false
+}
+
+fn same_rule(a: &Rule, b: &Rule) -> bool {
+    a.r#match == b.r#match
+        && a.regex == b.regex
+        && a.case_insensitive == b.case_insensitive
+        && a.excludes == b.excludes
+        && a.requires == b.requires
+}
+
+/// Whether every title `later` matches is already accepted by `earlier`.
+///
+/// Only substring rules are compared; regexes can't be checked in general.
+fn shadows(earlier: &Rule, later: &Rule) -> bool {
+    if earlier.regex
+        || later.regex
+        || !earlier.excludes.is_empty()
+        || !earlier.requires.all.is_empty()
+        || !earlier.requires.any.is_empty()
+    {
+        return false;
+    }
+
+    if earlier.case_insensitive {
+        later
+            .r#match
+            .to_lowercase()
+            .contains(&earlier.r#match.to_lowercase())
+    } else {
+        !later.case_insensitive && later.r#match.contains(&earlier.r#match)
+    }
+}
+
+/// The message of `err`, without the position it ends with.
+fn message(err: &yaml_serde::Error) -> String {
+    let message = err.to_string();
+
+    let Some(x) = err.location() else {
+        return message;
+    };
+
+    match message.strip_suffix(&format!(" at line {} column {}", x.line(), x.column())) {
+        Some(r) => r.to_owned(),
+        None => message,
+    }
+}
+
+/// Parses channels from `text`, turning the ones that don't deserialize into diagnostics so the
+/// rest can still be validated.
+///
+/// serde stops at the first error, and only that one comes with a position. The others are found
+/// by parsing each channel, and each of its rules, on its own; they are positioned like in
+/// [`validate`].
+pub fn parse(source: &str, text: &str) -> (Vec<ChannelConfig>, Vec<Diagnostic>) {
+    let first = match yaml_serde::from_str::<Vec<ChannelConfig>>(text) {
+        Ok(channels) => return (channels, Vec::new()),
+        Err(err) => err,
+    };
+
+    let values = match yaml_serde::from_str::<Vec<Value>>(text) {
+        Ok(r) => r,
+        Err(err) => {
+            let diagnostic = Diagnostic {
+                source: source.to_owned(),
+                location: err.location().map(Location::from),
+                channel: None,
+                rule: None,
+                message: message(&err),
+            };
+
+            return (Vec::new(), vec![diagnostic]);
+        }
+    };
+
+    let mut first = Some((message(&first), first.location().map(Location::from)));
+    let mut channels = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut cursor = 0;
+
+    for mut value in values {
+        let url = value
+            .get("url")
+            .and_then(Value::as_str)
+            .map(|x| x.to_owned());
+        let channel_offset = url.as_deref().and_then(|url| find(text, url, cursor));
+        cursor = channel_offset.unwrap_or(cursor);
+
+        // (rule, offset, message)
+        let mut problems = Vec::new();
+
+        // rules that don't parse are left out, so the rest of the channel is still checked
+        if let Some(rules) = value.get_mut("rules").and_then(Value::as_sequence_mut) {
+            let mut rule_cursor = cursor;
+
+            rules.retain(|rule| {
+                let rule_offset = find_key(text, "match", rule_cursor);
+                rule_cursor = rule_offset.map(|x| x + 1).unwrap_or(rule_cursor);
+
+                let Err(err) = yaml_serde::from_value::<Rule>(rule.clone()) else {
+                    return true;
+                };
+
+                let name = rule.get("match").and_then(Value::as_str);
+                problems.push((name.map(|x| x.to_owned()), rule_offset, message(&err)));
+
+                false
+            });
+        }
+
+        match yaml_serde::from_value::<ChannelConfig>(value) {
+            Ok(channel) if problems.is_empty() => channels.push(channel),
+            Ok(_) => {}
+            Err(err) => problems.push((None, channel_offset, message(&err))),
+        }
+
+        for (rule, offset, message) in problems {
+            let location = match first.take_if(|(first, _)| first.ends_with(&message)) {
+                Some((_, location)) => location,
+                None => offset.or(channel_offset).map(|x| location(text, x)),
+            };
+
+            diagnostics.push(Diagnostic {
+                source: source.to_owned(),
+                location,
+                channel: Some(url.clone().unwrap_or_default()),
+                rule,
+                message,
+            });
+        }
+    }
+
+    (channels, diagnostics)
+}
+
+/// Checks channels parsed from `text`. Positions are found by searching `text` for each channel's
+/// `url` and the `match` key of each of its rules, so a channel whose `url` is written with YAML
+/// escapes has no position.
+pub fn validate(source: &str, text: &str, channels: &[ChannelConfig]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut cursor = 0;
+
+    for channel in channels {
+        let channel_offset = find(text, &channel.url, cursor);
+        cursor = channel_offset.unwrap_or(cursor);
+
+        for (name, value) in &channel.headers {
+            let message = if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                format!("invalid header name `{name}`")
+            } else if HeaderValue::from_str(value.expose()).is_err() {
+                format!("invalid value of header `{name}`")
+            } else {
+                continue;
+            };
+
+            diagnostics.push(Diagnostic {
+                source: source.to_owned(),
+                location: find_key(text, name, cursor).map(|x| location(text, x)),
+                channel: Some(channel.url.clone()),
+                rule: None,
+                message,
+            });
+        }
+
+        let mut rule_cursor = cursor;
+
+        for (i, rule) in channel.rules.iter().enumerate() {
+            let rule_offset = find_key(text, "match", rule_cursor);
+            rule_cursor = rule_offset.map(|x| x + 1).unwrap_or(rule_cursor);
+
+            let mut report = |message: String| {
+                diagnostics.push(Diagnostic {
+                    source: source.to_owned(),
+                    location: rule_offset.or(channel_offset).map(|x| location(text, x)),
+                    channel: Some(channel.url.clone()),
+                    rule: Some(rule.r#match.clone()),
+                    message,
+                })
+            };
+
+            if rule.r#match.trim().is_empty() {
+                report("empty `match`".to_owned());
+            }
+
+            if rule.starts_episode_at < 0 {
+                report(format!(
+                    "negative `episode` offset {}",
+                    rule.starts_episode_at
+                ));
+            }
+
+            if escapes(&rule.directory) {
+                report(format!(
+                    "`directory` {} escapes the channel directory {}",
+                    rule.directory.display(),
+                    channel.directory.display()
+                ));
+            }
+
+            let earlier = &channel.rules[..i];
+
+            if let Some(j) = earlier.iter().position(|x| same_rule(x, rule)) {
+                report(format!("duplicate of rule #{}", j + 1));
+            } else if let Some(j) = earlier.iter().position(|x| shadows(x, rule)) {
+                report(format!(
+                    "unreachable, shadowed by rule #{} `{}`",
+                    j + 1,
+                    earlier[j].r#match
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+#[test]
+fn test_validate() {
+    let text = "
+- url: https://nyaa.si/?page=rss
+  directory: /downloads
+  rules:
+    - match: Slime
+      directory: Slime
+    - match: Slime
+      directory: Slime
+    - match: Slime Datta Ken
+      directory: ../../etc
+    - match: ''
+      episode: -1
+      directory: /etc
+";
+
+    let channels = yaml_serde::from_str::<Vec<ChannelConfig>>(text).unwrap();
+
+    let diagnostics = validate("channels.yaml", text, &channels)
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            "channels.yaml:7:7: channel https://nyaa.si/?page=rss, rule `Slime`: duplicate of rule #1",
+            "channels.yaml:9:7: channel https://nyaa.si/?page=rss, rule `Slime Datta Ken`: `directory` ../../etc escapes the channel directory /downloads",
+            "channels.yaml:9:7: channel https://nyaa.si/?page=rss, rule `Slime Datta Ken`: unreachable, shadowed by rule #1 `Slime`",
+            "channels.yaml:11:7: channel https://nyaa.si/?page=rss, rule ``: empty `match`",
+            "channels.yaml:11:7: channel https://nyaa.si/?page=rss, rule ``: negative `episode` offset -1",
+            "channels.yaml:11:7: channel https://nyaa.si/?page=rss, rule ``: `directory` /etc escapes the channel directory /downloads",
+        ],
+        diagnostics
+    );
+}
+
+#[test]
+fn test_validate_fallthrough_is_not_shadowed() {
+    let text = "
+- url: https://nyaa.si/?page=rss
+  directory: /downloads
+  rules:
+    - match: Slime
+      excludes: [HEVC]
+      directory: Slime
+    - match: Slime
+      directory: Slime (HEVC)
+    - match: slime
+      case_insensitive: true
+      directory: Slime (other)
+";
+
+    let channels = yaml_serde::from_str::<Vec<ChannelConfig>>(text).unwrap();
+
+    assert!(validate("channels.yaml", text, &channels).is_empty());
+}
+
+#[test]
+fn test_validate_headers() {
+    let text = "
+- url: https://tracker.example.com/rss
+  directory: /downloads
+  headers:
+    X-Api-Key: abc
+    Bad Header: x
+  rules: []
+";
+
+    let channels = yaml_serde::from_str::<Vec<ChannelConfig>>(text).unwrap();
+
+    let diagnostics = validate("channels.yaml", text, &channels)
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec!["channels.yaml:6:5: channel https://tracker.example.com/rss: invalid header name `Bad Header`"],
+        diagnostics
+    );
+}